@@ -0,0 +1,50 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
+use interp::tokenizer::tokenize;
+
+/// A chunk of script touching every kind of token the lexer produces.
+const SAMPLE: &str = r#"/// Sums the even numbers below `limit`.
+function sum_evens(limit) {
+    var total is 0 # running sum
+    for (var i is 0, i < limit, i = i + 1) {
+        if i mod 2 = 0 and not (i >= 1000) {
+            total = total + i
+        } else if i <= 3 or i not= 7 { continue }
+    }
+    return total
+}
+
+/* Build a few values /* nested */ to look at. */
+var names is ["Ann", 'Bob', "Café\t\u{1F600}", r"C:\raw\path"]
+var person is { name: "Ann", age: 42, score: 3.75 }
+each key, value in person {
+    print("${key} is ${value}")
+}
+outer: while true {
+    loop { break outer }
+}
+var double is function (n) { return n * 2 }
+print(double(sum_evens(100)) / 4 - 1)
+"#;
+
+/// Repeats `SAMPLE` until the script is at least `bytes` long.
+fn script(bytes: usize) -> String {
+    let mut source = String::with_capacity(bytes + SAMPLE.len());
+    while source.len() < bytes {
+        source.push_str(SAMPLE);
+    }
+    source
+}
+
+fn lexer(c: &mut Criterion) {
+    let source = script(4 * 1024 * 1024);
+    let mut group = c.benchmark_group("tokenize");
+    group.throughput(Throughput::Bytes(source.len() as u64));
+    group.sample_size(20);
+    group.bench_function("4MiB script", |b| {
+        b.iter(|| tokenize(black_box(&source)).unwrap())
+    });
+    group.finish();
+}
+
+criterion_group!(benches, lexer);
+criterion_main!(benches);
@@ -1,91 +1,393 @@
-use crate::token::Token;
+use crate::token::Span;
 use crate::token::TokenType;
-use lazy_static::lazy_static;
-use phf::Map;
-use std::collections::BTreeMap;
+use crate::value::Value;
+use std::cell::RefCell;
 use std::collections::HashMap;
-use std::collections::LinkedList;
-use std::ops::DerefMut;
-use std::sync::Mutex;
+use std::rc::Rc;
 
-lazy_static! {
+/// A scope shared by the interpreter and every closure created in it.
+pub type Frame = Rc<RefCell<Scope>>;
 
+#[derive(Clone, Debug)]
+pub struct Scope {
+    pub symbols: HashMap<String, Value>,
+}
 
-    #[derive(Clone, Debug)]
-    pub static ref CALL_STACK: Mutex<LinkedList<Scope>> = Mutex::new(LinkedList::<Scope>::new());
-    static ref FUNCTION_TABLE: Mutex<Map<String, Vec<Token>>> = Mutex::new(Map::<String, Vec<Token>>::new());
+impl Default for Scope {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
-#[derive(Clone, Debug, Eq, Hash, PartialEq, PartialOrd, Ord)]
-pub enum SymbolType {
-    Number(String),
-    String(String),
-    Object(BTreeMap<SymbolType, SymbolType>),
-    Array(Vec<SymbolType>),
-    Function(String),
-    Pointer(String),
+impl Scope {
+    pub fn new() -> Scope {
+        Scope {
+            symbols: HashMap::new(),
+        }
+    }
+
+    pub fn add(&mut self, name: String, symbol: Value) {
+        self.symbols.insert(name, symbol);
+    }
+
+    pub fn into_frame(self) -> Frame {
+        Rc::new(RefCell::new(self))
+    }
 }
 
-#[derive(Clone, Debug)]
-pub struct FunctionSymbolArgs {
-    pub args: Vec<Token>,
-    pub body: LinkedList<Token>,
+/// A parsed source file: the statements to run, in order.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Program {
+    pub body: Vec<Stmt>,
 }
 
-#[derive(Clone, Debug)]
-pub struct ObjectSymbolArgs {
-    pub vars: HashMap<String, SymbolType>,
-    pub methods: HashMap<String, FunctionSymbolArgs>,
+/// A brace-delimited list of statements. Blocks introduce a new scope.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Block {
+    pub body: Vec<Stmt>,
+    pub span: Span,
 }
 
-#[derive(Clone, Debug)]
-pub struct ArraySymbolArgs {
-    pub array: Vec<SymbolType>,
+#[derive(Clone, Debug, PartialEq)]
+pub enum Stmt {
+    /// `var name is value`
+    VarDecl {
+        name: String,
+        value: Expr,
+        span: Span,
+    },
+    /// `target = value`, where `target` is a variable, element or field.
+    Assign {
+        target: Expr,
+        value: Expr,
+        span: Span,
+    },
+    /// `{ ... }` on its own.
+    Block(Block),
+    /// `if condition { ... } else { ... }`; an `else if` chain nests another
+    /// `If` as the only statement of `else_branch`.
+    If {
+        condition: Expr,
+        then_branch: Block,
+        else_branch: Option<Block>,
+        span: Span,
+    },
+    While {
+        label: Option<String>,
+        condition: Expr,
+        body: Block,
+        span: Span,
+    },
+    /// `for (init, condition, step) { ... }`
+    For {
+        label: Option<String>,
+        init: Box<Stmt>,
+        condition: Expr,
+        step: Box<Stmt>,
+        body: Block,
+        span: Span,
+    },
+    Loop {
+        label: Option<String>,
+        body: Block,
+        span: Span,
+    },
+    /// `each item in collection { ... }` or `each key, item in collection
+    /// { ... }`. The key is the index for arrays and strings and the field
+    /// name for objects; with a single name, objects bind the field name.
+    Each {
+        label: Option<String>,
+        key: Option<String>,
+        item: String,
+        collection: Expr,
+        body: Block,
+        span: Span,
+    },
+    /// `break` or `break label`, leaving the innermost loop or the loop
+    /// with that label.
+    Break {
+        label: Option<String>,
+        span: Span,
+    },
+    /// `continue` or `continue label`.
+    Continue {
+        label: Option<String>,
+        span: Span,
+    },
+    FunctionDecl {
+        name: String,
+        params: Vec<String>,
+        body: Rc<Block>,
+        span: Span,
+    },
+    Return {
+        value: Option<Expr>,
+        span: Span,
+    },
+    ExprStmt(Expr),
 }
 
-#[derive(Clone, Debug)]
-pub struct Scope {
-    pub symbols: HashMap<String, SymbolType>,
+#[derive(Clone, Debug, PartialEq)]
+pub enum Expr {
+    /// A number, boolean, null or string written in the source.
+    Literal {
+        value: Value,
+        span: Span,
+    },
+    Variable {
+        name: String,
+        span: Span,
+    },
+    Unary {
+        op: TokenType,
+        operand: Box<Expr>,
+        span: Span,
+    },
+    Binary {
+        op: TokenType,
+        left: Box<Expr>,
+        right: Box<Expr>,
+        span: Span,
+    },
+    Call {
+        callee: Box<Expr>,
+        args: Vec<Expr>,
+        span: Span,
+    },
+    /// `target[index]`
+    Index {
+        target: Box<Expr>,
+        index: Box<Expr>,
+        span: Span,
+    },
+    /// `target[start:end]`, where either bound may be left out.
+    Slice {
+        target: Box<Expr>,
+        start: Option<Box<Expr>>,
+        end: Option<Box<Expr>>,
+        span: Span,
+    },
+    /// `target.name`
+    Member {
+        target: Box<Expr>,
+        name: String,
+        span: Span,
+    },
+    Array {
+        elements: Vec<Expr>,
+        span: Span,
+    },
+    /// `function (params) { ... }`
+    Function {
+        params: Vec<String>,
+        body: Rc<Block>,
+        span: Span,
+    },
+    /// `"text ${expr} text"`: the parts are evaluated in order and their
+    /// text joined. Literal text between interpolations is a string part.
+    Template {
+        parts: Vec<Expr>,
+        span: Span,
+    },
+    /// `{ key: value, ... }`, keys kept in source order.
+    Object {
+        entries: Vec<(Expr, Expr)>,
+        span: Span,
+    },
 }
 
-impl Scope {
-    pub fn new() -> Scope {
-        Scope {
-            symbols: HashMap::new(),
+impl Stmt {
+    pub fn span(&self) -> Span {
+        match self {
+            Stmt::VarDecl { span, .. }
+            | Stmt::Assign { span, .. }
+            | Stmt::If { span, .. }
+            | Stmt::While { span, .. }
+            | Stmt::For { span, .. }
+            | Stmt::Loop { span, .. }
+            | Stmt::Each { span, .. }
+            | Stmt::Break { span, .. }
+            | Stmt::Continue { span, .. }
+            | Stmt::FunctionDecl { span, .. }
+            | Stmt::Return { span, .. } => *span,
+            Stmt::Block(block) => block.span,
+            Stmt::ExprStmt(expr) => expr.span(),
         }
     }
+}
 
-    pub fn add(&mut self, name: String, symbol: SymbolType) {
-        self.symbols.insert(name, symbol);
+impl Expr {
+    pub fn span(&self) -> Span {
+        match self {
+            Expr::Literal { span, .. }
+            | Expr::Variable { span, .. }
+            | Expr::Unary { span, .. }
+            | Expr::Binary { span, .. }
+            | Expr::Call { span, .. }
+            | Expr::Index { span, .. }
+            | Expr::Slice { span, .. }
+            | Expr::Member { span, .. }
+            | Expr::Array { span, .. }
+            | Expr::Function { span, .. }
+            | Expr::Template { span, .. }
+            | Expr::Object { span, .. } => *span,
+        }
     }
 }
 
-impl CALL_STACK {
-    pub fn push(&self, scope: Scope) {
-        CALL_STACK.lock().unwrap().push_back(scope);
+/// A statement or expression waiting to be dropped. Syntax trees can nest
+/// deeper than the native stack allows recursion, so dropping one moves
+/// the children of every node onto an explicit work list instead.
+enum Node {
+    Stmt(Stmt),
+    Expr(Expr),
+}
+
+/// Stands in for a child moved out of a boxed field.
+fn placeholder() -> Expr {
+    Expr::Literal {
+        value: Value::Null,
+        span: Span::default(),
     }
+}
+
+fn take_block(block: &mut Block, pending: &mut Vec<Node>) {
+    pending.extend(block.body.drain(..).map(Node::Stmt));
+}
 
-    pub fn pop(&self) {
-        CALL_STACK.lock().unwrap().pop_back();
+/// A function body is only dropped with the last function sharing it.
+fn take_body(body: &mut Rc<Block>, pending: &mut Vec<Node>) {
+    if let Some(block) = Rc::get_mut(body) {
+        take_block(block, pending);
     }
+}
 
-    pub fn add_symbol(&self, name: String, symbol: SymbolType) {
-        let mut stack = CALL_STACK.lock().unwrap();
-        if stack.is_empty() {
-            stack.push_back(Scope::new());
+fn take_expr(expr: &mut Expr, pending: &mut Vec<Node>) {
+    pending.push(Node::Expr(std::mem::replace(expr, placeholder())));
+}
+
+impl Stmt {
+    fn take_children(&mut self, pending: &mut Vec<Node>) {
+        match self {
+            Stmt::VarDecl { value, .. } => take_expr(value, pending),
+            Stmt::Assign { target, value, .. } => {
+                take_expr(target, pending);
+                take_expr(value, pending);
+            }
+            Stmt::Block(block) => take_block(block, pending),
+            Stmt::If {
+                condition,
+                then_branch,
+                else_branch,
+                ..
+            } => {
+                take_expr(condition, pending);
+                take_block(then_branch, pending);
+                if let Some(block) = else_branch {
+                    take_block(block, pending);
+                }
+            }
+            Stmt::While {
+                condition, body, ..
+            } => {
+                take_expr(condition, pending);
+                take_block(body, pending);
+            }
+            Stmt::For {
+                init,
+                condition,
+                step,
+                body,
+                ..
+            } => {
+                take_expr(condition, pending);
+                take_block(body, pending);
+                for stmt in [init, step] {
+                    let stmt = std::mem::replace(&mut **stmt, Stmt::ExprStmt(placeholder()));
+                    pending.push(Node::Stmt(stmt));
+                }
+            }
+            Stmt::Loop { body, .. } => take_block(body, pending),
+            Stmt::Each {
+                collection, body, ..
+            } => {
+                take_expr(collection, pending);
+                take_block(body, pending);
+            }
+            Stmt::FunctionDecl { body, .. } => take_body(body, pending),
+            Stmt::Return {
+                value: Some(value), ..
+            } => take_expr(value, pending),
+            Stmt::ExprStmt(expr) => take_expr(expr, pending),
+            Stmt::Break { .. } | Stmt::Continue { .. } | Stmt::Return { value: None, .. } => {}
         }
-        let mut scope = stack.back_mut().unwrap();
-        scope.add(name, symbol);
-        println!("{:?}", scope);
     }
+}
 
-    pub fn lookup_symbol(&self, name: String) -> Option<SymbolType> {
-        let stack = CALL_STACK.lock().unwrap();
-        for scope in stack.iter().rev() {
-            if let Some(symbol) = scope.symbols.get(&name) {
-                return Some(symbol.clone());
+impl Expr {
+    fn take_children(&mut self, pending: &mut Vec<Node>) {
+        match self {
+            Expr::Literal { .. } | Expr::Variable { .. } => {}
+            Expr::Unary { operand: child, .. } | Expr::Member { target: child, .. } => {
+                take_expr(child, pending)
+            }
+            Expr::Binary { left, right, .. } => {
+                take_expr(left, pending);
+                take_expr(right, pending);
+            }
+            Expr::Index { target, index, .. } => {
+                take_expr(target, pending);
+                take_expr(index, pending);
             }
+            Expr::Slice {
+                target, start, end, ..
+            } => {
+                take_expr(target, pending);
+                pending.extend(start.take().into_iter().map(|expr| Node::Expr(*expr)));
+                pending.extend(end.take().into_iter().map(|expr| Node::Expr(*expr)));
+            }
+            Expr::Call { callee, args, .. } => {
+                take_expr(callee, pending);
+                pending.extend(args.drain(..).map(Node::Expr));
+            }
+            Expr::Array {
+                elements: children, ..
+            }
+            | Expr::Template {
+                parts: children, ..
+            } => pending.extend(children.drain(..).map(Node::Expr)),
+            Expr::Object { entries, .. } => {
+                for (key, value) in entries.drain(..) {
+                    pending.push(Node::Expr(key));
+                    pending.push(Node::Expr(value));
+                }
+            }
+            Expr::Function { body, .. } => take_body(body, pending),
+        }
+    }
+}
+
+fn drop_children(mut pending: Vec<Node>) {
+    while let Some(mut node) = pending.pop() {
+        match &mut node {
+            Node::Stmt(stmt) => stmt.take_children(&mut pending),
+            Node::Expr(expr) => expr.take_children(&mut pending),
         }
-        None
+    }
+}
+
+impl Drop for Stmt {
+    fn drop(&mut self) {
+        let mut pending = Vec::new();
+        self.take_children(&mut pending);
+        drop_children(pending);
+    }
+}
+
+impl Drop for Expr {
+    fn drop(&mut self) {
+        let mut pending = Vec::new();
+        self.take_children(&mut pending);
+        drop_children(pending);
     }
 }
@@ -0,0 +1,82 @@
+use crate::ast::Scope;
+use crate::error::{LetscriptError, Result};
+use crate::token::Span;
+use crate::value::{NativeFunction, Value};
+
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+
+const BUILTINS: &[NativeFunction] = &[
+    NativeFunction {
+        name: "print",
+        arity: None,
+        call: print,
+    },
+    NativeFunction {
+        name: "type",
+        arity: Some(1),
+        call: type_of,
+    },
+    NativeFunction {
+        name: "has",
+        arity: Some(2),
+        call: has,
+    },
+    NativeFunction {
+        name: "delete",
+        arity: Some(2),
+        call: delete,
+    },
+];
+
+/// A frame binding every native function under its name.
+pub fn scope() -> Scope {
+    let mut scope = Scope::new();
+    for native in BUILTINS {
+        scope.add(native.name.to_string(), Value::NativeFunction(*native));
+    }
+    scope
+}
+
+/// Writes the arguments to stdout separated by spaces.
+fn print(args: &[Value], _span: Span) -> Result<Value> {
+    let line: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+    println!("{}", line.join(" "));
+    Ok(Value::Null)
+}
+
+fn type_of(args: &[Value], _span: Span) -> Result<Value> {
+    Ok(Value::Str(args[0].type_name().to_string()))
+}
+
+/// The object and field name passed to `has` or `delete`.
+fn object_field<'a>(
+    name: &str,
+    args: &'a [Value],
+    span: Span,
+) -> Result<(&'a RefCell<BTreeMap<String, Value>>, &'a str)> {
+    match (&args[0], &args[1]) {
+        (Value::Object(fields), Value::Str(key)) => Ok((fields, key)),
+        (object, key) => Err(LetscriptError::type_error(
+            span,
+            format!(
+                "'{}' expects an object and a string, not {} and {}",
+                name,
+                object.type_name(),
+                key.type_name()
+            ),
+        )),
+    }
+}
+
+/// Whether the object has the field.
+fn has(args: &[Value], span: Span) -> Result<Value> {
+    let (fields, key) = object_field("has", args, span)?;
+    Ok(Value::Bool(fields.borrow().contains_key(key)))
+}
+
+/// Removes the field from the object, returning whether it was there.
+fn delete(args: &[Value], span: Span) -> Result<Value> {
+    let (fields, key) = object_field("delete", args, span)?;
+    Ok(Value::Bool(fields.borrow_mut().remove(key).is_some()))
+}
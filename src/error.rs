@@ -0,0 +1,109 @@
+use crate::token::Span;
+use std::fmt;
+
+/// Every failure the tokenizer, parser or evaluator can report. Each
+/// variant carries the span of the source text that caused it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum LetscriptError {
+    /// The tokenizer met text that is not part of the language.
+    Lex { message: String, span: Span },
+    /// The tokens do not form a valid program.
+    Parse { message: String, span: Span },
+    /// An operator was applied to a value it does not support.
+    Type { message: String, span: Span },
+    /// Evaluation failed for any other reason.
+    Runtime { message: String, span: Span },
+    /// A variable was used before it was declared.
+    Name { name: String, span: Span },
+}
+
+pub type Result<T> = std::result::Result<T, LetscriptError>;
+
+impl LetscriptError {
+    pub fn lex(span: Span, message: impl Into<String>) -> LetscriptError {
+        LetscriptError::Lex {
+            message: message.into(),
+            span,
+        }
+    }
+
+    pub fn parse(span: Span, message: impl Into<String>) -> LetscriptError {
+        LetscriptError::Parse {
+            message: message.into(),
+            span,
+        }
+    }
+
+    pub fn type_error(span: Span, message: impl Into<String>) -> LetscriptError {
+        LetscriptError::Type {
+            message: message.into(),
+            span,
+        }
+    }
+
+    pub fn runtime(span: Span, message: impl Into<String>) -> LetscriptError {
+        LetscriptError::Runtime {
+            message: message.into(),
+            span,
+        }
+    }
+
+    pub fn name(span: Span, name: impl Into<String>) -> LetscriptError {
+        LetscriptError::Name {
+            name: name.into(),
+            span,
+        }
+    }
+
+    pub fn span(&self) -> Span {
+        match self {
+            LetscriptError::Lex { span, .. }
+            | LetscriptError::Parse { span, .. }
+            | LetscriptError::Type { span, .. }
+            | LetscriptError::Runtime { span, .. }
+            | LetscriptError::Name { span, .. } => *span,
+        }
+    }
+
+    /// Formats the error as `file:line:column: message` followed by the
+    /// offending source line with the span underlined. Spans carry no file
+    /// name, so `file` names the source the error came from.
+    pub fn report(&self, file: &str, source: &str) -> String {
+        let span = self.span();
+        let mut report = format!("{}:{}: {}", file, span, self.describe());
+        if let Some(line) = source.lines().nth(span.line.saturating_sub(1)) {
+            let line = line.trim_end_matches('\r');
+            let indent = span.column.saturating_sub(1);
+            let width = source
+                .get(span.start..span.end)
+                .map(|text| text.lines().next().unwrap_or("").chars().count())
+                .unwrap_or(0)
+                .max(1);
+            report.push_str(&format!(
+                "\n    {}\n    {}{}",
+                line,
+                " ".repeat(indent),
+                "^".repeat(width)
+            ));
+        }
+        report
+    }
+
+    fn describe(&self) -> String {
+        match self {
+            LetscriptError::Lex { message, .. } => format!("lex error: {}", message),
+            LetscriptError::Parse { message, .. } => format!("parse error: {}", message),
+            LetscriptError::Type { message, .. } => format!("type error: {}", message),
+            LetscriptError::Runtime { message, .. } => format!("runtime error: {}", message),
+            LetscriptError::Name { name, .. } => format!("name error: '{}' is not defined", name),
+        }
+    }
+}
+
+impl fmt::Display for LetscriptError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.span(), self.describe())
+    }
+}
+
+impl std::error::Error for LetscriptError {}
@@ -1,383 +1,486 @@
-use regex::Regex;
+use crate::error::{LetscriptError, Result};
+use crate::token::{Span, Token, TokenType};
+use crate::value::Value;
+use num_bigint::{BigInt, Sign};
+use num_traits::{One, Pow, ToPrimitive, Zero};
+use std::cmp::Ordering;
+use std::rc::Rc;
 
-use crate::token::{Token, TokenType, TokenValue};
+/// The longest string, in bytes, that repeating a string may produce.
+pub const MAX_STRING_LENGTH: usize = 1 << 30;
 
-#[derive(Clone, Copy, Debug)]
-pub enum LSExpr {
-    Int32(i32),
-    Float64(f64),
-    Boolean(bool),
-    Undefined,
-    Null,
-    AddI32(i32, i32),
-    AddI32F64(i32, f64),
-    AddF64(f64, f64),
-    SubtractI32(i32, i32),
-    SubtractF64(f64, f64),
-    SubtractI32F64(i32, f64),
-    SubtractF64I32(f64, i32),
-    MultiplyI32(i32, i32),
-    MultiplyI32F64(i32, f64),
-    MultiplyF64(f64, f64),
-    DivideI32(i32, i32),
-    DivideI32F64(i32, f64),
-    DivideF64I32(f64, i32),
-    DivideF64(f64, f64),
-    ModI32(i32, i32),
-    ModI32F64(i32, f64),
-    ModF64I32(f64, i32),
-    ModF64(f64, f64),
-    And(bool, bool),
-    Or(bool, bool),
-    Equal(bool, bool),
-    NotEqual(bool, bool),
-    Gt(bool, bool),
-    Lt(bool, bool),
-    Gte(bool, bool),
-    Lte(bool, bool),
-    EqualI32(i32, i32),
-    NotEqualI32(i32, i32),
-    GtI32(i32, i32),
-    LtI32(i32, i32),
-    GteI32(i32, i32),
-    LteI32(i32, i32),
-    EqualF64(f64, f64),
-    NotEqualF64(f64, f64),
-    GtF64(f64, f64),
-    LtF64(f64, f64),
-    GteF64(f64, f64),
-    LteF64(f64, f64),
-    Negate(bool),
+/// The largest integer power, in bits, that `**` will compute.
+pub const MAX_POWER_BITS: u64 = 1 << 22;
+
+/// What integer arithmetic does with a result that does not fit in an
+/// `i64`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Overflow {
+    /// Continue with an arbitrary-precision integer.
+    #[default]
+    Promote,
+    /// Stop with a runtime error.
+    Error,
 }
 
-impl LSExpr {
-    fn eval(&self) -> LSExpr {
-        match self {
-            LSExpr::AddF64(l, r) => return LSExpr::Float64(l + r),
-            LSExpr::AddI32F64(l, r) => return LSExpr::Float64(*l as f64 + r),
-            LSExpr::AddI32(l, r) => return LSExpr::Int32(l + r),
-            LSExpr::SubtractF64(l, r) => return LSExpr::Float64(l - r),
-            LSExpr::SubtractI32F64(l, r) => return LSExpr::Float64(*l as f64 - r),
-            LSExpr::SubtractF64I32(l, r) => return LSExpr::Float64(*l - *r as f64),
-            LSExpr::SubtractI32(l, r) => return LSExpr::Int32(l - r),
-            LSExpr::MultiplyF64(l, r) => return LSExpr::Float64(l * r),
-            LSExpr::MultiplyI32F64(l, r) => return LSExpr::Float64(*l as f64 * r),
-            LSExpr::MultiplyI32(l, r) => return LSExpr::Int32(l * r),
-            LSExpr::DivideF64(l, r) => return LSExpr::Float64(l / r),
-            LSExpr::DivideI32F64(l, r) => return LSExpr::Float64(*l as f64 / r),
-            LSExpr::DivideI32(l, r) => return LSExpr::Int32(l / r),
-            LSExpr::DivideF64I32(l, r) => return LSExpr::Float64(*l / *r as f64),
-            LSExpr::ModI32(l, r) => return LSExpr::Int32(*l % *r),
-            LSExpr::ModF64(l, r) => return LSExpr::Float64(*l % *r),
-            LSExpr::ModI32F64(l, r) => return LSExpr::Float64(*l as f64 % *r),
-            LSExpr::ModF64I32(l, r) => return LSExpr::Float64(*l % *r as f64),
-            LSExpr::And(l, r) => return LSExpr::Boolean(*l && *r),
-            LSExpr::Or(l, r) => return LSExpr::Boolean(*l || *r),
-            LSExpr::Equal(l, r) => return LSExpr::Boolean(*l == *r),
-            LSExpr::NotEqual(l, r) => return LSExpr::Boolean(*l != *r),
-            LSExpr::Gt(l, r) => return LSExpr::Boolean(*l > *r),
-            LSExpr::Lt(l, r) => return LSExpr::Boolean(*l < *r),
-            LSExpr::Gte(l, r) => return LSExpr::Boolean(*l >= *r),
-            LSExpr::Lte(l, r) => return LSExpr::Boolean(*l <= *r),
-            LSExpr::EqualI32(l, r) => return LSExpr::Boolean(*l == *r),
-            LSExpr::NotEqualI32(l, r) => return LSExpr::Boolean(*l != *r),
-            LSExpr::GtI32(l, r) => return LSExpr::Boolean(*l > *r),
-            LSExpr::LtI32(l, r) => return LSExpr::Boolean(*l < *r),
-            LSExpr::GteI32(l, r) => return LSExpr::Boolean(*l >= *r),
-            LSExpr::LteI32(l, r) => return LSExpr::Boolean(*l <= *r),
-            LSExpr::EqualF64(l, r) => return LSExpr::Boolean(*l == *r),
-            LSExpr::NotEqualF64(l, r) => return LSExpr::Boolean(*l != *r),
-            LSExpr::GtF64(l, r) => return LSExpr::Boolean(*l > *r),
-            LSExpr::LtF64(l, r) => return LSExpr::Boolean(*l < *r),
-            LSExpr::GteF64(l, r) => return LSExpr::Boolean(*l >= *r),
-            LSExpr::LteF64(l, r) => return LSExpr::Boolean(*l <= *r),
-            LSExpr::Negate(v) => return LSExpr::Boolean(!*v),
-            _ => return LSExpr::Undefined,
-        };
-    }
-    fn cast_to_bool(&self) -> LSExpr {
-        match self {
-            LSExpr::Int32(x) => {
-                if *x == 0 {
-                    LSExpr::Boolean(false)
-                } else {
-                    LSExpr::Boolean(true)
-                }
-            }
-            LSExpr::Float64(x) => {
-                if *x == 0.0 {
-                    LSExpr::Boolean(false)
-                } else {
-                    LSExpr::Boolean(true)
-                }
-            }
-            LSExpr::Null => LSExpr::Boolean(false),
-            LSExpr::Boolean(x) => LSExpr::Boolean(*x),
-            _ => LSExpr::Undefined,
+/// The two operands of a numeric operator after coercion: integers stay
+/// integers, and a float on either side makes both floats.
+enum Numbers {
+    Ints(i64, i64),
+    Bigs(BigInt, BigInt),
+    Floats(f64, f64),
+}
+
+fn numbers(left: &Value, right: &Value) -> Option<Numbers> {
+    let float = |value: &Value| match value {
+        Value::Int(x) => Some(*x as f64),
+        Value::BigInt(x) => x.to_f64(),
+        Value::Float(x) => Some(*x),
+        _ => None,
+    };
+    match (left, right) {
+        (Value::Int(l), Value::Int(r)) => Some(Numbers::Ints(*l, *r)),
+        (Value::Int(_) | Value::BigInt(_), Value::Int(_) | Value::BigInt(_)) => {
+            Some(Numbers::Bigs(big(left)?, big(right)?))
         }
+        _ => Some(Numbers::Floats(float(left)?, float(right)?)),
     }
+}
 
-    fn cast_to_float(&self) -> LSExpr {
-        match self {
-            LSExpr::Int32(x) => LSExpr::Float64(*x as f64),
-            LSExpr::Float64(x) => LSExpr::Float64(*x),
-            LSExpr::Null => LSExpr::Null,
-            LSExpr::Boolean(x) => {
-                if *x == false {
-                    LSExpr::Float64(1.0)
-                } else {
-                    LSExpr::Float64(0.0)
-                }
-            }
-            _ => LSExpr::Undefined,
-        }
+/// The integer value of `value` as a big integer, if it is an integer.
+fn big(value: &Value) -> Option<BigInt> {
+    match value {
+        Value::Int(x) => Some(BigInt::from(*x)),
+        Value::BigInt(x) => Some(x.clone()),
+        _ => None,
     }
+}
 
-    fn cast_to_int(&self) -> LSExpr {
-        match self {
-            LSExpr::Int32(x) => LSExpr::Int32(*x),
-            LSExpr::Float64(x) => LSExpr::Int32(*x as i32),
-            LSExpr::Null => LSExpr::Null,
-            LSExpr::Boolean(x) => {
-                if *x == false {
-                    LSExpr::Int32(1)
-                } else {
-                    LSExpr::Int32(0)
-                }
-            }
-            _ => LSExpr::Undefined,
+type StringOperation = fn(&Value, &Value, &Token) -> Result<Value>;
+
+/// An arithmetic operator on each of the representations of numbers. The
+/// `i64` version returns `None` when the result does not fit.
+#[derive(Clone, Copy)]
+struct Arithmetic {
+    int: fn(i64, i64) -> Option<i64>,
+    big: fn(&BigInt, &BigInt) -> BigInt,
+    float: fn(f64, f64) -> f64,
+    /// Whether an integer right operand of zero is an error.
+    divides: bool,
+    /// The operation when either operand is a string, if it has one.
+    strings: Option<StringOperation>,
+}
+
+/// How a binary operator treats its operands. Ints include big integers,
+/// and "number" means an int or a float.
+#[derive(Clone, Copy)]
+enum Rule {
+    /// Two ints give an int; integer division truncates toward zero and
+    /// `mod` takes the sign of the left operand. Results outside the `i64`
+    /// range follow [`Overflow`], and dividing an int by zero is a runtime
+    /// error. A float on either side makes the operation a float one, with
+    /// IEEE 754 results: a non-zero number divided by zero is `inf` or
+    /// `-inf`, while `0 / 0.0` and any modulo by zero are `NaN`.
+    ///
+    /// `+` joins two strings, and `*` repeats a string by an int on either
+    /// side; a negative count is a runtime error. Strings and numbers are
+    /// never converted into each other, so any other operand, such as in
+    /// `"a" + 1`, is a type error. Interpolation turns values into text.
+    Arithmetic(Arithmetic),
+    /// Like `Arithmetic`, except that an int raised to a negative int is a
+    /// float, and an int exponent too large for a `u32` is a runtime error
+    /// unless the base is -1, 0 or 1.
+    Power,
+    /// Two ints compare exactly; a float on either side compares both as
+    /// floats, and every comparison with `NaN` is false. Two strings
+    /// compare lexicographically by Unicode code point. Any other operand,
+    /// including a string against a number, is a type error.
+    Comparison(fn(Ordering) -> bool),
+    /// Structural equality; see [`equal`]. Never an error. The flag is the
+    /// result for equal operands.
+    Equality(bool),
+    /// `is`: whether both operands are the same value. Arrays, objects and
+    /// functions are the same only if they are one value reached through
+    /// different names, however equal their contents. Other values are the
+    /// same when they have the same type and are equal, so `1 is 1.0` is
+    /// false. Never an error.
+    Identity,
+    /// Applies to the truth values of both operands, as used for
+    /// conditions: zero and null are false, other numbers are true, and
+    /// undefined or any other type is a type error.
+    Logical(fn(bool, bool) -> bool),
+    /// See [`contains`].
+    Membership,
+}
+
+/// Every binary operator and its rule.
+static OPERATORS: [(TokenType, Rule); 16] = [
+    (
+        TokenType::Plus,
+        Rule::Arithmetic(Arithmetic {
+            int: i64::checked_add,
+            big: |l, r| l + r,
+            float: |l, r| l + r,
+            divides: false,
+            strings: Some(concatenate),
+        }),
+    ),
+    (
+        TokenType::Minus,
+        Rule::Arithmetic(Arithmetic {
+            int: i64::checked_sub,
+            big: |l, r| l - r,
+            float: |l, r| l - r,
+            divides: false,
+            strings: None,
+        }),
+    ),
+    (
+        TokenType::Mult,
+        Rule::Arithmetic(Arithmetic {
+            int: i64::checked_mul,
+            big: |l, r| l * r,
+            float: |l, r| l * r,
+            divides: false,
+            strings: Some(repeat),
+        }),
+    ),
+    (
+        TokenType::Div,
+        Rule::Arithmetic(Arithmetic {
+            int: i64::checked_div,
+            big: |l, r| l / r,
+            float: |l, r| l / r,
+            divides: true,
+            strings: None,
+        }),
+    ),
+    (
+        TokenType::Mod,
+        Rule::Arithmetic(Arithmetic {
+            int: i64::checked_rem,
+            big: |l, r| l % r,
+            float: |l, r| l % r,
+            divides: true,
+            strings: None,
+        }),
+    ),
+    (TokenType::Pow, Rule::Power),
+    (TokenType::GreaterThan, Rule::Comparison(Ordering::is_gt)),
+    (TokenType::LessThan, Rule::Comparison(Ordering::is_lt)),
+    (TokenType::Gte, Rule::Comparison(Ordering::is_ge)),
+    (TokenType::Lte, Rule::Comparison(Ordering::is_le)),
+    (TokenType::Equals, Rule::Equality(true)),
+    (TokenType::NotEqual, Rule::Equality(false)),
+    (TokenType::Is, Rule::Identity),
+    (TokenType::And, Rule::Logical(|l, r| l && r)),
+    (TokenType::Or, Rule::Logical(|l, r| l || r)),
+    (TokenType::In, Rule::Membership),
+];
+
+/// The truth value of `value` as a condition. This follows the same rules
+/// as the operands of `and`, `or` and `not`: zero and null are false, other
+/// numbers are true, and any other value is a type error.
+pub fn truthiness(value: &Value, span: Span) -> Result<bool> {
+    match value {
+        Value::Int(x) => Ok(*x != 0),
+        // Big integers are never zero.
+        Value::BigInt(_) => Ok(true),
+        Value::Float(x) => Ok(*x != 0.0),
+        Value::Bool(x) => Ok(*x),
+        Value::Null => Ok(false),
+        _ => Err(LetscriptError::type_error(
+            span,
+            format!(
+                "a value of type {} cannot be used as a condition",
+                value.type_name()
+            ),
+        )),
+    }
+}
+
+/// `item in collection`: element equality for arrays, key lookup for
+/// objects and substring search for strings.
+fn contains(item: &Value, collection: &Value, operator: &Token) -> Result<Value> {
+    let found = match (item, collection) {
+        (_, Value::Array(items)) => items.borrow().iter().any(|x| equal(item, x)),
+        (Value::Str(key), Value::Object(fields)) => fields.borrow().contains_key(key),
+        (Value::Str(needle), Value::Str(haystack)) => haystack.contains(needle.as_str()),
+        _ => {
+            return Err(LetscriptError::type_error(
+                operator.span,
+                format!(
+                    "cannot look for a value of type {} in a value of type {}",
+                    item.type_name(),
+                    collection.type_name()
+                ),
+            ))
         }
+    };
+    Ok(Value::Bool(found))
+}
+
+fn division_by_zero(operator: &Token) -> LetscriptError {
+    LetscriptError::runtime(
+        operator.span,
+        format!("integer division by zero in '{}'", operator.tok_type),
+    )
+}
+
+fn overflow_error(operator: &Token) -> LetscriptError {
+    LetscriptError::runtime(
+        operator.span,
+        format!("integer overflow in '{}'", operator.tok_type),
+    )
+}
+
+/// Applies a binary operator following its rule in `OPERATORS`.
+pub fn operate(left: &Value, right: &Value, operator: &Token, overflow: Overflow) -> Result<Value> {
+    let rule = OPERATORS
+        .iter()
+        .find(|(tok_type, _)| *tok_type == operator.tok_type)
+        .map(|(_, rule)| *rule);
+    match rule {
+        Some(Rule::Arithmetic(op)) => arithmetic(op, left, right, operator, overflow),
+        Some(Rule::Power) => power(left, right, operator, overflow),
+        Some(Rule::Comparison(accepts)) => compare(accepts, left, right, operator),
+        Some(Rule::Equality(when_equal)) => Ok(Value::Bool(equal(left, right) == when_equal)),
+        Some(Rule::Identity) => Ok(Value::Bool(identical(left, right))),
+        Some(Rule::Logical(combine)) => Ok(Value::Bool(combine(
+            logical_operand(left, operator)?,
+            logical_operand(right, operator)?,
+        ))),
+        Some(Rule::Membership) => contains(left, right, operator),
+        None => Err(LetscriptError::runtime(
+            operator.span,
+            format!("'{}' is not a binary operator", operator.tok_type),
+        )),
     }
 }
 
-fn convert_token_to_value(t: Token) -> LSExpr {
-    let int_regx = Regex::new(r"^(\d+\b|-\d+\b)$").unwrap();
-    let float_regx = Regex::new(r"^(\d+\.\d+\b|-\d+\.\d+\b)$").unwrap();
-    let bool_regx = Regex::new(r"^(true\b|false\b)$").unwrap();
-    let null_regx = Regex::new(r"^null$").unwrap();
-    let undef_regx = Regex::new(r"^undefined$").unwrap();
-    let val = t.tok_value.unwrap().s_val.unwrap();
-    println!("{}", val);
-    if int_regx.is_match(&val) {
-        return LSExpr::Int32(val.parse::<i32>().ok().unwrap());
-    } else if float_regx.is_match(&val) {
-        return LSExpr::Float64(val.parse::<f64>().ok().unwrap());
-    } else if bool_regx.is_match(&val) {
-        return LSExpr::Boolean(val.parse::<bool>().ok().unwrap());
-    } else if null_regx.is_match(&val) {
-        return LSExpr::Null;
-    } else {
-        return LSExpr::Undefined;
+fn arithmetic(
+    op: Arithmetic,
+    left: &Value,
+    right: &Value,
+    operator: &Token,
+    overflow: Overflow,
+) -> Result<Value> {
+    if let (Some(strings), true) = (op.strings, is_string(left) || is_string(right)) {
+        return strings(left, right, operator);
+    }
+    match numbers(left, right) {
+        Some(Numbers::Ints(_, 0)) if op.divides => Err(division_by_zero(operator)),
+        Some(Numbers::Ints(l, r)) => match (op.int)(l, r) {
+            Some(x) => Ok(Value::Int(x)),
+            None if overflow == Overflow::Error => Err(overflow_error(operator)),
+            None => Ok(Value::from_big((op.big)(
+                &BigInt::from(l),
+                &BigInt::from(r),
+            ))),
+        },
+        Some(Numbers::Bigs(_, r)) if op.divides && r.is_zero() => Err(division_by_zero(operator)),
+        Some(Numbers::Bigs(l, r)) => Ok(Value::from_big((op.big)(&l, &r))),
+        Some(Numbers::Floats(l, r)) => Ok(Value::Float((op.float)(l, r))),
+        None => Err(operand_error(left, right, operator)),
     }
 }
 
-pub fn operate(left: Token, right: Token, operator: Token) -> Token {
-    let l = convert_token_to_value(left);
-    let r = convert_token_to_value(right);
-    let mut expr = LSExpr::Undefined;
-    match (l, r, operator.tok_type) {
-        (LSExpr::Int32(x), LSExpr::Int32(y), TokenType::Plus) => expr = LSExpr::AddI32(x, y).eval(),
-        (LSExpr::Int32(x), LSExpr::Float64(y), TokenType::Plus) => {
-            expr = LSExpr::AddI32F64(x, y).eval()
-        }
-        (LSExpr::Float64(x), LSExpr::Int32(y), TokenType::Plus) => {
-            expr = LSExpr::AddI32F64(y, x).eval()
-        }
-        (LSExpr::Float64(x), LSExpr::Float64(y), TokenType::Plus) => {
-            expr = LSExpr::AddF64(y, x).eval()
-        }
-        (LSExpr::Int32(x), LSExpr::Int32(y), TokenType::Minus) => {
-            expr = LSExpr::SubtractI32(x, y).eval()
-        }
-        (LSExpr::Int32(x), LSExpr::Float64(y), TokenType::Minus) => {
-            expr = LSExpr::SubtractI32F64(x, y).eval()
-        }
-        (LSExpr::Float64(x), LSExpr::Int32(y), TokenType::Minus) => {
-            expr = LSExpr::SubtractI32F64(y, x).eval()
-        }
-        (LSExpr::Float64(x), LSExpr::Float64(y), TokenType::Minus) => {
-            expr = LSExpr::SubtractF64(y, x).eval()
-        }
-        (LSExpr::Int32(x), LSExpr::Int32(y), TokenType::Mult) => {
-            expr = LSExpr::MultiplyI32(x, y).eval()
-        }
-        (LSExpr::Int32(x), LSExpr::Float64(y), TokenType::Mult) => {
-            println!("x:{}\ny:{}", x, y);
-            expr = LSExpr::MultiplyI32F64(x, y).eval();
-            println!("expr:{:?}", &expr);
-        }
-        (LSExpr::Float64(x), LSExpr::Int32(y), TokenType::Mult) => {
-            expr = LSExpr::MultiplyI32F64(y, x).eval()
-        }
-        (LSExpr::Float64(x), LSExpr::Float64(y), TokenType::Mult) => {
-            expr = LSExpr::MultiplyF64(y, x).eval()
-        }
-        (LSExpr::Int32(x), LSExpr::Int32(y), TokenType::Div) => {
-            expr = LSExpr::DivideI32(x, y).eval()
-        }
-        (LSExpr::Int32(x), LSExpr::Float64(y), TokenType::Div) => {
-            expr = LSExpr::DivideI32F64(x, y).eval()
-        }
-        (LSExpr::Float64(x), LSExpr::Int32(y), TokenType::Div) => {
-            expr = LSExpr::DivideF64I32(x, y).eval()
-        }
-        (LSExpr::Float64(x), LSExpr::Float64(y), TokenType::Div) => {
-            expr = LSExpr::DivideF64(y, x).eval()
-        }
-        (LSExpr::Int32(x), LSExpr::Int32(y), TokenType::Mod) => expr = LSExpr::ModI32(x, y).eval(),
-        (LSExpr::Int32(x), LSExpr::Float64(y), TokenType::Mod) => {
-            expr = LSExpr::ModI32F64(x, y).eval()
-        }
-        (LSExpr::Float64(x), LSExpr::Int32(y), TokenType::Mod) => {
-            expr = LSExpr::ModI32F64(y, x).eval()
-        }
-        (LSExpr::Float64(x), LSExpr::Float64(y), TokenType::Mod) => {
-            expr = LSExpr::ModF64(y, x).eval()
+fn power(left: &Value, right: &Value, operator: &Token, overflow: Overflow) -> Result<Value> {
+    let big_power = |l: BigInt, r: BigInt| match r.to_u32() {
+        // Only these bases have powers of any size small enough to compute.
+        _ if l.is_zero() || l.is_one() => Ok(Value::from_big(l)),
+        _ if l == BigInt::from(-1) => Ok(Value::Int(if r.bit(0) { -1 } else { 1 })),
+        Some(r) if l.bits().saturating_mul(u64::from(r)) <= MAX_POWER_BITS => {
+            Ok(Value::from_big(l.pow(r)))
         }
+        _ => Err(LetscriptError::runtime(
+            operator.span,
+            format!("exponent {} is too large", r),
+        )),
+    };
+    match numbers(left, right) {
+        Some(Numbers::Ints(l, r)) if r < 0 => Ok(Value::Float((l as f64).powf(r as f64))),
+        Some(Numbers::Ints(l, r)) => match u32::try_from(r).map(|exp| l.checked_pow(exp)) {
+            Ok(Some(x)) => Ok(Value::Int(x)),
+            Ok(None) if overflow == Overflow::Error => Err(overflow_error(operator)),
+            _ => big_power(BigInt::from(l), BigInt::from(r)),
+        },
+        Some(Numbers::Bigs(l, r)) if r.sign() == Sign::Minus => Ok(Value::Float(
+            l.to_f64()
+                .unwrap_or(f64::NAN)
+                .powf(r.to_f64().unwrap_or(f64::NAN)),
+        )),
+        Some(Numbers::Bigs(l, r)) => big_power(l, r),
+        Some(Numbers::Floats(l, r)) => Ok(Value::Float(l.powf(r))),
+        None => Err(operand_error(left, right, operator)),
+    }
+}
 
-        (LSExpr::Boolean(x), LSExpr::Boolean(y), TokenType::And) => expr = LSExpr::And(y, x).eval(),
-        (LSExpr::Boolean(x), LSExpr::Boolean(y), TokenType::Or) => expr = LSExpr::Or(y, x).eval(),
-        (_, _, TokenType::And) => {
-            expr = LSExpr::And(
-                match l.cast_to_bool() {
-                    LSExpr::Boolean(x) => x,
-                    _ => panic!("got non bool value"),
-                },
-                match r.cast_to_bool() {
-                    LSExpr::Boolean(x) => x,
-                    _ => panic!("got non bool value"),
-                },
-            )
-            .eval()
-        }
-        (_, _, TokenType::Or) => {
-            expr = LSExpr::Or(
-                match l.cast_to_bool() {
-                    LSExpr::Boolean(x) => x,
-                    _ => panic!("got non bool value"),
-                },
-                match r.cast_to_bool() {
-                    LSExpr::Boolean(x) => x,
-                    _ => panic!("got non bool value"),
-                },
-            )
-            .eval()
-        }
-        (_, _, TokenType::GreaterThan) => {
-            expr = LSExpr::GtF64(
-                match l.cast_to_float() {
-                    LSExpr::Float64(x) => x,
-                    _ => panic!("got non bool value"),
-                },
-                match r.cast_to_float() {
-                    LSExpr::Float64(x) => x,
-                    _ => panic!("got non bool value"),
-                },
-            )
-            .eval()
-        }
-        (_, _, TokenType::LessThan) => {
-            expr = LSExpr::LtF64(
-                match l.cast_to_float() {
-                    LSExpr::Float64(x) => x,
-                    _ => panic!("got non bool value"),
-                },
-                match r.cast_to_float() {
-                    LSExpr::Float64(x) => x,
-                    _ => panic!("got non bool value"),
-                },
-            )
-            .eval()
-        }
-        (_, _, TokenType::Gte) => {
-            expr = LSExpr::GteF64(
-                match l.cast_to_float() {
-                    LSExpr::Float64(x) => x,
-                    _ => panic!("got non bool value"),
-                },
-                match r.cast_to_float() {
-                    LSExpr::Float64(x) => x,
-                    _ => panic!("got non bool value"),
-                },
-            )
-            .eval()
-        }
-        (_, _, TokenType::Lte) => {
-            expr = LSExpr::LteF64(
-                match l.cast_to_float() {
-                    LSExpr::Float64(x) => x,
-                    _ => panic!("got non bool value"),
-                },
-                match r.cast_to_float() {
-                    LSExpr::Float64(x) => x,
-                    _ => panic!("got non bool value"),
-                },
-            )
-            .eval()
+fn compare(
+    accepts: fn(Ordering) -> bool,
+    left: &Value,
+    right: &Value,
+    operator: &Token,
+) -> Result<Value> {
+    let ordering = match (left, right) {
+        (Value::Str(l), Value::Str(r)) => Some(l.cmp(r)),
+        _ => match numbers(left, right) {
+            Some(Numbers::Ints(l, r)) => Some(l.cmp(&r)),
+            Some(Numbers::Bigs(l, r)) => Some(l.cmp(&r)),
+            Some(Numbers::Floats(l, r)) => l.partial_cmp(&r),
+            None => return Err(operand_error(left, right, operator)),
+        },
+    };
+    Ok(Value::Bool(ordering.is_some_and(accepts)))
+}
+
+fn is_string(value: &Value) -> bool {
+    matches!(value, Value::Str(_))
+}
+
+fn concatenate(left: &Value, right: &Value, operator: &Token) -> Result<Value> {
+    match (left, right) {
+        (Value::Str(l), Value::Str(r)) => Ok(Value::Str(format!("{}{}", l, r))),
+        _ => Err(operand_error(left, right, operator)),
+    }
+}
+
+fn repeat(left: &Value, right: &Value, operator: &Token) -> Result<Value> {
+    let (text, count) = match (left, right) {
+        (Value::Str(text), count @ (Value::Int(_) | Value::BigInt(_)))
+        | (count @ (Value::Int(_) | Value::BigInt(_)), Value::Str(text)) => (text, count),
+        _ => return Err(operand_error(left, right, operator)),
+    };
+    let count = big(count).unwrap();
+    if count.sign() == Sign::Minus {
+        return Err(LetscriptError::runtime(
+            operator.span,
+            "cannot repeat a string a negative number of times",
+        ));
+    }
+    match count
+        .to_usize()
+        .filter(|n| text.len().saturating_mul(*n) <= MAX_STRING_LENGTH)
+    {
+        Some(n) => Ok(Value::Str(text.repeat(n))),
+        None => Err(LetscriptError::runtime(
+            operator.span,
+            "the repeated string would be too long",
+        )),
+    }
+}
+
+/// Equality as tested by `=`. Numbers are equal when their values are, so
+/// `1 = 1.0`, and `NaN` equals nothing. Arrays are equal when they have
+/// equal elements in the same order and objects when they have the same
+/// keys with equal values, compared the same way all the way down; an
+/// array or object always equals itself. Strings, booleans and functions
+/// equal values of their own type only. `null` equals only `null` and
+/// `undefined` only `undefined`, so `null = undefined`, `null = 0` and
+/// `undefined = false` are all false.
+pub fn equal(left: &Value, right: &Value) -> bool {
+    equal_within(left, right, &mut Vec::new())
+}
+
+/// `equal`, where `comparing` holds the pairs of arrays or objects whose
+/// comparison is in progress. Meeting one of them again means the values
+/// contain themselves; that pair is taken to be equal, and the rest of the
+/// comparison decides.
+fn equal_within(left: &Value, right: &Value, comparing: &mut Vec<(*const (), *const ())>) -> bool {
+    let pair = match (left, right) {
+        (Value::Array(l), Value::Array(r)) if !Rc::ptr_eq(l, r) => {
+            (Rc::as_ptr(l) as *const (), Rc::as_ptr(r) as *const ())
         }
-        (_, _, TokenType::Equals) => {
-            expr = LSExpr::EqualF64(
-                match l.cast_to_float() {
-                    LSExpr::Float64(x) => x,
-                    _ => panic!("got non bool value"),
-                },
-                match r.cast_to_float() {
-                    LSExpr::Float64(x) => x,
-                    _ => panic!("got non bool value"),
-                },
-            )
-            .eval()
+        (Value::Object(l), Value::Object(r)) if !Rc::ptr_eq(l, r) => {
+            (Rc::as_ptr(l) as *const (), Rc::as_ptr(r) as *const ())
         }
+        (Value::Array(_), Value::Array(_)) | (Value::Object(_), Value::Object(_)) => return true,
         _ => {
-            println!(
-                "l: {:?}, r: {:?}, op: {:?} is not a valid binary expr",
-                l, r, operator.tok_type
-            );
-            expr = LSExpr::Undefined;
+            return match numbers(left, right) {
+                Some(Numbers::Ints(l, r)) => l == r,
+                Some(Numbers::Bigs(l, r)) => l == r,
+                Some(Numbers::Floats(l, r)) => l == r,
+                None => left == right,
+            }
         }
+    };
+    if comparing.contains(&pair) {
+        return true;
     }
-    return Token {
-        tok_type: TokenType::Literal,
-        tok_value: Some(TokenValue {
-            s_val: match expr {
-                LSExpr::Int32(x) => Some(x.to_string()),
-                LSExpr::Float64(x) => Some(x.to_string()),
-                LSExpr::Boolean(x) => Some(x.to_string()),
-                LSExpr::Null => None,
-                _ => Some(String::from("Undefined")),
-            },
-        }),
+    comparing.push(pair);
+    let result = match (left, right) {
+        (Value::Array(l), Value::Array(r)) => {
+            let (l, r) = (l.borrow(), r.borrow());
+            l.len() == r.len()
+                && l.iter()
+                    .zip(r.iter())
+                    .all(|(l, r)| equal_within(l, r, comparing))
+        }
+        (Value::Object(l), Value::Object(r)) => {
+            let (l, r) = (l.borrow(), r.borrow());
+            l.len() == r.len()
+                && l.iter()
+                    .zip(r.iter())
+                    .all(|((lk, lv), (rk, rv))| lk == rk && equal_within(lv, rv, comparing))
+        }
+        _ => unreachable!(),
     };
+    comparing.pop();
+    result
 }
 
-pub fn operate_unary(value: Token, operator: Token) -> Token {
-    let val = convert_token_to_value(value);
-    let expr;
-    match operator.tok_type {
-        TokenType::Not => {
-            let b_val = val.cast_to_bool();
-            expr = match b_val {
-                LSExpr::Boolean(v) => LSExpr::Negate(v).eval(),
-                _ => {
-                    panic!("unknown unary type")
-                }
-            };
-        }
-        _ => {
-            panic!("unknown unary")
-        }
+/// Identity as tested by `is`; see [`Rule::Identity`].
+fn identical(left: &Value, right: &Value) -> bool {
+    match (left, right) {
+        (Value::Array(l), Value::Array(r)) => Rc::ptr_eq(l, r),
+        (Value::Object(l), Value::Object(r)) => Rc::ptr_eq(l, r),
+        _ => left == right,
     }
+}
 
-    return Token {
-        tok_type: TokenType::Literal,
-        tok_value: Some(TokenValue {
-            s_val: match expr {
-                LSExpr::Int32(x) => Some(x.to_string()),
-                LSExpr::Float64(x) => Some(x.to_string()),
-                LSExpr::Boolean(x) => Some(x.to_string()),
-                LSExpr::Null => None,
-                _ => Some(String::from("Undefined")),
-            },
-        }),
-    };
+/// The truth value of an operand of `and` or `or`.
+pub fn logical_operand(value: &Value, operator: &Token) -> Result<bool> {
+    truthiness(value, operator.span).map_err(|_| {
+        LetscriptError::type_error(
+            operator.span,
+            format!(
+                "'{}' cannot be applied to a value of type {}",
+                operator.tok_type,
+                value.type_name()
+            ),
+        )
+    })
+}
+
+fn operand_error(left: &Value, right: &Value, operator: &Token) -> LetscriptError {
+    LetscriptError::type_error(
+        operator.span,
+        format!(
+            "'{}' cannot be applied to values of type {} and {}",
+            operator.tok_type,
+            left.type_name(),
+            right.type_name()
+        ),
+    )
+}
+
+pub fn operate_unary(value: &Value, operator: &Token, overflow: Overflow) -> Result<Value> {
+    match (value, operator.tok_type) {
+        (Value::Int(x), TokenType::Minus) => match x.checked_neg() {
+            Some(x) => Ok(Value::Int(x)),
+            None if overflow == Overflow::Error => Err(overflow_error(operator)),
+            None => Ok(Value::from_big(-BigInt::from(*x))),
+        },
+        (Value::BigInt(x), TokenType::Minus) => Ok(Value::from_big(-x)),
+        (Value::Float(x), TokenType::Minus) => Ok(Value::Float(-x)),
+        (_, TokenType::Minus) => Err(LetscriptError::type_error(
+            operator.span,
+            format!("cannot negate a value of type {}", value.type_name()),
+        )),
+        (_, TokenType::Not) => Ok(Value::Bool(!logical_operand(value, operator)?)),
+        _ => Err(LetscriptError::runtime(
+            operator.span,
+            format!("'{}' is not a unary operator", operator.tok_type),
+        )),
+    }
 }
@@ -0,0 +1,719 @@
+use crate::ast::{Block, Expr, Frame, Program, Scope, Stmt};
+use crate::builtins;
+use crate::error::{LetscriptError, Result};
+use crate::expr::{logical_operand, operate, operate_unary, truthiness, Overflow};
+use crate::token::Span;
+use crate::token::Token;
+use crate::token::TokenType;
+use crate::value::{Function, Home, Value};
+use crate::{STACK_GROWTH, STACK_RED_ZONE};
+
+use num_bigint::Sign;
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::rc::Rc;
+
+/// How deeply script functions may call each other before a call fails
+/// with a runtime error.
+pub const MAX_CALL_DEPTH: usize = 10_000;
+
+/// How a statement finished. `break` and `continue` unwind through
+/// enclosing blocks until they reach the loop they target, and `return`
+/// until it reaches the function call.
+#[derive(Debug)]
+enum Flow {
+    Normal,
+    Break(Option<String>, Span),
+    Continue(Option<String>, Span),
+    Return(Value, Span),
+}
+
+/// Decides what a loop does after one run of its body: `None` to go on
+/// with the next iteration, or the flow to finish the loop with.
+fn after_iteration(flow: Flow, label: &Option<String>) -> Option<Flow> {
+    let targets_this_loop = |target: &Option<String>| target.is_none() || target == label;
+    match flow {
+        Flow::Normal => None,
+        Flow::Continue(target, _) if targets_this_loop(&target) => None,
+        Flow::Break(target, _) if targets_this_loop(&target) => Some(Flow::Normal),
+        other => Some(other),
+    }
+}
+
+/// The `(key, item)` pairs visited by `each`: index and element for
+/// arrays, index and one-character string for strings, and field name and
+/// value for objects in key order. Arrays and objects are iterated as they
+/// are when the loop starts.
+fn iteration_pairs(collection: Value, span: Span) -> Result<Vec<(Value, Value)>> {
+    let index = |i: usize| Value::Int(i as i64);
+    match &collection {
+        Value::Array(items) => Ok(items
+            .borrow()
+            .iter()
+            .cloned()
+            .enumerate()
+            .map(|(i, item)| (index(i), item))
+            .collect()),
+        Value::Str(text) => Ok(text
+            .chars()
+            .enumerate()
+            .map(|(i, c)| (index(i), Value::Str(c.to_string())))
+            .collect()),
+        Value::Object(fields) => Ok(fields
+            .borrow()
+            .iter()
+            .map(|(name, value)| (Value::Str(name.clone()), value.clone()))
+            .collect()),
+        other => Err(LetscriptError::type_error(
+            span,
+            format!("cannot iterate over a value of type {}", other.type_name()),
+        )),
+    }
+}
+
+/// The position `index` refers to in a sequence of `len` elements, where
+/// negative indices count back from the end.
+fn position(index: &Value, len: usize, span: Span) -> Result<usize> {
+    let out_of_bounds = || {
+        LetscriptError::runtime(
+            span,
+            format!("index {} is out of bounds for length {}", index, len),
+        )
+    };
+    let i = match index {
+        Value::Int(i) => *i,
+        Value::BigInt(_) => return Err(out_of_bounds()),
+        other => {
+            return Err(LetscriptError::type_error(
+                span,
+                format!("an index must be an int, not a {}", other.type_name()),
+            ))
+        }
+    };
+    let position = if i < 0 { len as i64 + i } else { i };
+    if (0..len as i64).contains(&position) {
+        Ok(position as usize)
+    } else {
+        Err(out_of_bounds())
+    }
+}
+
+/// A slice bound as a position in a sequence of `len` elements. Negative
+/// bounds count back from the end, and bounds past either end are clamped.
+fn slice_bound(bound: Option<Value>, default: usize, len: usize, span: Span) -> Result<usize> {
+    let position = match &bound {
+        None => default as i64,
+        Some(Value::Int(i)) if *i < 0 => len as i64 + i,
+        Some(Value::Int(i)) => *i,
+        Some(Value::BigInt(i)) if i.sign() == Sign::Minus => 0,
+        Some(Value::BigInt(_)) => len as i64,
+        Some(other) => {
+            return Err(LetscriptError::type_error(
+                span,
+                format!("a slice bound must be an int, not a {}", other.type_name()),
+            ))
+        }
+    };
+    Ok(position.clamp(0, len as i64) as usize)
+}
+
+/// The key an object is indexed with, which must be a string.
+fn key(index: &Value, span: Span) -> Result<&str> {
+    match index {
+        Value::Str(key) => Ok(key),
+        other => Err(LetscriptError::type_error(
+            span,
+            format!(
+                "an object key must be a string, not a {}",
+                other.type_name()
+            ),
+        )),
+    }
+}
+
+/// The value of the field `name` of an object, or `target.name`.
+fn field(target: &Value, name: &str, span: Span) -> Result<Value> {
+    match target {
+        Value::Object(fields) => fields.borrow().get(name).cloned().ok_or_else(|| {
+            LetscriptError::runtime(span, format!("the object has no field '{}'", name))
+        }),
+        other => Err(LetscriptError::type_error(
+            span,
+            format!("a value of type {} has no fields", other.type_name()),
+        )),
+    }
+}
+
+/// Sets the field `name` of an object, adding it if it is missing.
+fn set_field(target: &Value, name: &str, value: Value, span: Span) -> Result<()> {
+    match target {
+        Value::Object(fields) => {
+            fields.borrow_mut().insert(name.to_string(), value);
+            Ok(())
+        }
+        other => Err(LetscriptError::type_error(
+            span,
+            format!(
+                "cannot assign to a field of a value of type {}",
+                other.type_name()
+            ),
+        )),
+    }
+}
+
+/// `target[index]` for arrays, for strings, whose elements are their
+/// characters, and for objects, indexed by field name.
+fn element(target: &Value, index: &Value, span: Span) -> Result<Value> {
+    match target {
+        Value::Object(_) => field(target, key(index, span)?, span),
+        Value::Array(items) => {
+            let items = items.borrow();
+            Ok(items[position(index, items.len(), span)?].clone())
+        }
+        Value::Str(text) => {
+            let i = position(index, text.chars().count(), span)?;
+            Ok(Value::Str(text.chars().nth(i).unwrap().to_string()))
+        }
+        other => Err(LetscriptError::type_error(
+            span,
+            format!("a value of type {} cannot be indexed", other.type_name()),
+        )),
+    }
+}
+
+/// `target[start:end]`: a new array or string with the elements from
+/// `start` up to but not including `end`, empty if `end` is not after
+/// `start`.
+fn slice(target: &Value, start: Option<Value>, end: Option<Value>, span: Span) -> Result<Value> {
+    let range = |len: usize| -> Result<(usize, usize)> {
+        let start = slice_bound(start, 0, len, span)?;
+        let end = slice_bound(end, len, len, span)?;
+        Ok((start, end.max(start)))
+    };
+    match target {
+        Value::Array(items) => {
+            let items = items.borrow();
+            let (start, end) = range(items.len())?;
+            Ok(Value::array(items[start..end].to_vec()))
+        }
+        Value::Str(text) => {
+            let (start, end) = range(text.chars().count())?;
+            Ok(Value::Str(
+                text.chars().skip(start).take(end - start).collect(),
+            ))
+        }
+        other => Err(LetscriptError::type_error(
+            span,
+            format!("a value of type {} cannot be sliced", other.type_name()),
+        )),
+    }
+}
+
+/// `target[index] = value`. Only array elements that exist can be
+/// replaced, while objects gain any field that is missing.
+fn set_element(target: &Value, index: &Value, value: Value, span: Span) -> Result<()> {
+    match target {
+        Value::Object(_) => set_field(target, key(index, span)?, value, span),
+        Value::Array(items) => {
+            let mut items = items.borrow_mut();
+            let i = position(index, items.len(), span)?;
+            items[i] = value;
+            Ok(())
+        }
+        other => Err(LetscriptError::type_error(
+            span,
+            format!(
+                "cannot assign to an element of a value of type {}",
+                other.type_name()
+            ),
+        )),
+    }
+}
+
+fn check_arity(name: &str, expected: usize, given: usize, span: Span) -> Result<()> {
+    if expected == given {
+        return Ok(());
+    }
+    Err(LetscriptError::runtime(
+        span,
+        format!(
+            "'{}' expects {} argument(s) but was given {}",
+            name, expected, given
+        ),
+    ))
+}
+
+/// The error for a `break`, `continue` or `return` that reached a point
+/// where nothing can handle it.
+fn stray_control(flow: Flow) -> LetscriptError {
+    let (label, span) = match flow {
+        Flow::Return(_, span) => {
+            return LetscriptError::runtime(span, "'return' can only be used inside a function")
+        }
+        Flow::Break(label, span) | Flow::Continue(label, span) => (label, span),
+        Flow::Normal => unreachable!("a normal flow is not an error"),
+    };
+    match label {
+        Some(label) => {
+            LetscriptError::runtime(span, format!("no enclosing loop is labelled '{}'", label))
+        }
+        None => LetscriptError::runtime(
+            span,
+            "'break' and 'continue' can only be used inside a loop",
+        ),
+    }
+}
+
+/// Called as `value` is stored in `frame`. A function stored in the frame
+/// it was defined in holds that frame weakly, so the two do not form a
+/// cycle.
+fn hold_home_weakly(frame: &Frame, value: &Value) {
+    if let Value::Function(function) = value {
+        if function.is_home(frame) {
+            function.hold_home(frame, true);
+        }
+    }
+}
+
+/// Executes a parsed program. Variables live in a stack of [`Scope`]
+/// frames: the first frame holds the builtins, the second globals, and
+/// every block pushes another for the duration of its body. Functions keep
+/// the frames in scope where they were defined, and a call runs the body on
+/// top of those instead of the caller's.
+pub struct Interpreter {
+    frames: Vec<Frame>,
+    depth: usize,
+    overflow: Overflow,
+}
+
+impl Default for Interpreter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Interpreter {
+    pub fn new() -> Interpreter {
+        Interpreter {
+            frames: vec![builtins::scope().into_frame(), Scope::new().into_frame()],
+            depth: 0,
+            overflow: Overflow::Promote,
+        }
+    }
+
+    /// Makes integer overflow a runtime error instead of switching to
+    /// arbitrary-precision integers.
+    pub fn strict(mut self) -> Interpreter {
+        self.overflow = Overflow::Error;
+        self
+    }
+
+    pub fn run(&mut self, program: &Program) -> Result<()> {
+        for stmt in &program.body {
+            match self.execute(stmt)? {
+                Flow::Normal => {}
+                flow => return Err(stray_control(flow)),
+            }
+        }
+        Ok(())
+    }
+
+    /// Finds the value bound to `name` in the innermost frame declaring it.
+    pub fn lookup(&self, name: &str) -> Option<Value> {
+        self.frames
+            .iter()
+            .rev()
+            .find_map(|frame| frame.borrow().symbols.get(name).cloned())
+    }
+
+    fn declare(&mut self, name: &str, value: Value) {
+        let frame = self.frames.last().unwrap();
+        hold_home_weakly(frame, &value);
+        frame.borrow_mut().add(name.to_string(), value);
+    }
+
+    fn assign(&mut self, name: &str, value: Value, span: Span) -> Result<()> {
+        for frame in self.frames.iter().rev() {
+            if let Some(slot) = frame.borrow_mut().symbols.get_mut(name) {
+                hold_home_weakly(frame, &value);
+                *slot = value;
+                return Ok(());
+            }
+        }
+        Err(LetscriptError::name(span, name))
+    }
+
+    fn execute(&mut self, stmt: &Stmt) -> Result<Flow> {
+        stacker::maybe_grow(STACK_RED_ZONE, STACK_GROWTH, || {
+            self.execute_statement(stmt)
+        })
+    }
+
+    fn execute_statement(&mut self, stmt: &Stmt) -> Result<Flow> {
+        match stmt {
+            Stmt::VarDecl { name, value, .. } => {
+                let value = self.evaluate(value)?;
+                self.declare(name, value);
+            }
+            Stmt::Assign { target, value, .. } => {
+                let value = self.evaluate(value)?;
+                match target {
+                    Expr::Variable { name, span } => self.assign(name, value, *span)?,
+                    Expr::Index {
+                        target,
+                        index,
+                        span,
+                    } => {
+                        let collection = self.evaluate(target)?;
+                        let index = self.evaluate(index)?;
+                        set_element(&collection, &index, value, *span)?;
+                    }
+                    Expr::Member { target, name, span } => {
+                        let object = self.evaluate(target)?;
+                        set_field(&object, name, value, *span)?;
+                    }
+                    _ => {
+                        return Err(LetscriptError::runtime(
+                            target.span(),
+                            "only variables, elements and fields can be assigned to",
+                        ))
+                    }
+                }
+            }
+            Stmt::Block(block) => return self.execute_block(block, Scope::new()),
+            Stmt::If {
+                condition,
+                then_branch,
+                else_branch,
+                ..
+            } => {
+                if self.condition(condition)? {
+                    return self.execute_block(then_branch, Scope::new());
+                } else if let Some(else_branch) = else_branch {
+                    return self.execute_block(else_branch, Scope::new());
+                }
+            }
+            Stmt::While {
+                label,
+                condition,
+                body,
+                ..
+            } => {
+                while self.condition(condition)? {
+                    let flow = self.execute_block(body, Scope::new())?;
+                    if let Some(flow) = after_iteration(flow, label) {
+                        return Ok(flow);
+                    }
+                }
+            }
+            Stmt::Loop { label, body, .. } => loop {
+                let flow = self.execute_block(body, Scope::new())?;
+                if let Some(flow) = after_iteration(flow, label) {
+                    return Ok(flow);
+                }
+            },
+            Stmt::For {
+                label,
+                init,
+                condition,
+                step,
+                body,
+                ..
+            } => {
+                // The initializer's variables belong to the loop, not to
+                // the enclosing block.
+                self.frames.push(Scope::new().into_frame());
+                let result = self.execute_for(label, init, condition, step, body);
+                self.pop_frame();
+                return result;
+            }
+            Stmt::Each {
+                label,
+                key,
+                item,
+                collection,
+                body,
+                ..
+            } => {
+                let value = self.evaluate(collection)?;
+                let keys_only = key.is_none() && matches!(value, Value::Object(_));
+                for (index, element) in iteration_pairs(value, collection.span())? {
+                    let mut scope = Scope::new();
+                    match key {
+                        Some(key) => {
+                            scope.add(key.clone(), index);
+                            scope.add(item.clone(), element);
+                        }
+                        None if keys_only => scope.add(item.clone(), index),
+                        None => scope.add(item.clone(), element),
+                    }
+                    let flow = self.execute_block(body, scope)?;
+                    if let Some(flow) = after_iteration(flow, label) {
+                        return Ok(flow);
+                    }
+                }
+            }
+            Stmt::Break { label, span } => return Ok(Flow::Break(label.clone(), *span)),
+            Stmt::Continue { label, span } => return Ok(Flow::Continue(label.clone(), *span)),
+            Stmt::FunctionDecl {
+                name, params, body, ..
+            } => {
+                let function = self.function(name, params, body);
+                self.declare(name, function);
+            }
+            Stmt::Return { value, span } => {
+                let value = match value {
+                    Some(value) => self.evaluate(value)?,
+                    None => Value::Null,
+                };
+                return Ok(Flow::Return(value, *span));
+            }
+            Stmt::ExprStmt(expr) => {
+                self.evaluate(expr)?;
+            }
+        }
+        Ok(Flow::Normal)
+    }
+
+    fn execute_for(
+        &mut self,
+        label: &Option<String>,
+        init: &Stmt,
+        condition: &Expr,
+        step: &Stmt,
+        body: &Block,
+    ) -> Result<Flow> {
+        self.execute(init)?;
+        while self.condition(condition)? {
+            let flow = self.execute_block(body, Scope::new())?;
+            if let Some(flow) = after_iteration(flow, label) {
+                return Ok(flow);
+            }
+            self.execute(step)?;
+        }
+        Ok(Flow::Normal)
+    }
+
+    /// Runs `block` in a new frame starting out with the bindings in
+    /// `scope`, stopping early at `break` or `continue`.
+    fn execute_block(&mut self, block: &Block, scope: Scope) -> Result<Flow> {
+        self.frames.push(scope.into_frame());
+        let mut result = Ok(Flow::Normal);
+        for stmt in &block.body {
+            result = self.execute(stmt);
+            if !matches!(result, Ok(Flow::Normal)) {
+                break;
+            }
+        }
+        self.pop_frame();
+        result
+    }
+
+    /// Pops the innermost frame. Functions stored in it hold it weakly; if
+    /// the frame or any of those functions is still referenced elsewhere,
+    /// the frame outlives its block, so they hold it strongly again.
+    fn pop_frame(&mut self) {
+        let frame = self.frames.pop().unwrap();
+        let scope = frame.borrow();
+        let homed: Vec<&Rc<Function>> = scope
+            .symbols
+            .values()
+            .filter_map(|value| match value {
+                Value::Function(function) if function.is_home(&frame) => Some(function),
+                _ => None,
+            })
+            .collect();
+        if Rc::strong_count(&frame) > 1 || homed.iter().any(|f| Rc::strong_count(f) > 1) {
+            for function in homed {
+                function.hold_home(&frame, false);
+            }
+        }
+    }
+
+    /// A function defined in the innermost frame.
+    fn function(&self, name: &str, params: &[String], body: &Rc<Block>) -> Value {
+        let (home, env) = self.frames.split_last().unwrap();
+        Value::Function(Rc::new(Function {
+            name: name.to_string(),
+            params: params.to_vec(),
+            body: body.clone(),
+            env: env.to_vec(),
+            home: RefCell::new(Home::Strong(home.clone())),
+        }))
+    }
+
+    fn condition(&mut self, condition: &Expr) -> Result<bool> {
+        let value = self.evaluate(condition)?;
+        truthiness(&value, condition.span())
+    }
+
+    fn call(&mut self, function: Value, args: &[Value], span: Span) -> Result<Value> {
+        match &function {
+            Value::NativeFunction(native) => {
+                if let Some(arity) = native.arity {
+                    check_arity(native.name, arity, args.len(), span)?;
+                }
+                (native.call)(args, span)
+            }
+            Value::Function(function) => {
+                check_arity(
+                    function.display_name(),
+                    function.params.len(),
+                    args.len(),
+                    span,
+                )?;
+                if self.depth == MAX_CALL_DEPTH {
+                    return Err(LetscriptError::runtime(
+                        span,
+                        format!(
+                            "maximum call depth of {} exceeded in '{}'",
+                            MAX_CALL_DEPTH,
+                            function.display_name()
+                        ),
+                    ));
+                }
+                let mut scope = Scope::new();
+                for (param, arg) in function.params.iter().zip(args) {
+                    scope.add(param.clone(), arg.clone());
+                }
+                let caller = std::mem::replace(&mut self.frames, function.frames());
+                self.depth += 1;
+                let result = self.execute_block(&function.body, scope);
+                self.depth -= 1;
+                self.frames = caller;
+                match result? {
+                    Flow::Normal => Ok(Value::Null),
+                    Flow::Return(value, _) => Ok(value),
+                    flow => Err(stray_control(flow)),
+                }
+            }
+            other => Err(LetscriptError::type_error(
+                span,
+                format!("a value of type {} is not callable", other.type_name()),
+            )),
+        }
+    }
+
+    fn evaluate(&mut self, expr: &Expr) -> Result<Value> {
+        stacker::maybe_grow(STACK_RED_ZONE, STACK_GROWTH, || {
+            self.evaluate_expression(expr)
+        })
+    }
+
+    fn evaluate_expression(&mut self, expr: &Expr) -> Result<Value> {
+        match expr {
+            Expr::Literal { value, .. } => Ok(value.clone()),
+            Expr::Variable { name, span } => match self.lookup(name) {
+                Some(value) => Ok(value.clone()),
+                None => Err(LetscriptError::name(*span, name)),
+            },
+            Expr::Unary { op, operand, span } => {
+                let value = self.evaluate(operand)?;
+                operate_unary(&value, &token(*op, *span), self.overflow)
+            }
+            Expr::Binary {
+                op: op @ (TokenType::And | TokenType::Or),
+                left,
+                right,
+                ..
+            } => {
+                // The right operand is only evaluated when the left one does
+                // not already decide the result.
+                let operator = token(*op, left.span().to(right.span()));
+                let decided = *op == TokenType::Or;
+                if logical_operand(&self.evaluate(left)?, &operator)? == decided {
+                    Ok(Value::Bool(decided))
+                } else {
+                    logical_operand(&self.evaluate(right)?, &operator).map(Value::Bool)
+                }
+            }
+            Expr::Binary {
+                op, left, right, ..
+            } => {
+                let l = self.evaluate(left)?;
+                let r = self.evaluate(right)?;
+                operate(
+                    &l,
+                    &r,
+                    &token(*op, left.span().to(right.span())),
+                    self.overflow,
+                )
+            }
+            Expr::Array { elements, .. } => Ok(Value::array(
+                elements
+                    .iter()
+                    .map(|element| self.evaluate(element))
+                    .collect::<Result<_>>()?,
+            )),
+            Expr::Template { parts, .. } => {
+                let mut text = String::new();
+                for part in parts {
+                    text.push_str(&self.evaluate(part)?.to_string());
+                }
+                Ok(Value::Str(text))
+            }
+            Expr::Object { entries, .. } => {
+                let mut object = BTreeMap::new();
+                for (key, value) in entries {
+                    let key = match &self.evaluate(key)? {
+                        Value::Str(key) => key.clone(),
+                        other => {
+                            return Err(LetscriptError::type_error(
+                                key.span(),
+                                format!("object keys must be strings, not {}", other.type_name()),
+                            ))
+                        }
+                    };
+                    let value = self.evaluate(value)?;
+                    object.insert(key, value);
+                }
+                Ok(Value::object(object))
+            }
+            Expr::Function { params, body, .. } => Ok(self.function("", params, body)),
+            Expr::Call { callee, args, span } => {
+                let function = self.evaluate(callee)?;
+                let args = args
+                    .iter()
+                    .map(|arg| self.evaluate(arg))
+                    .collect::<Result<Vec<_>>>()?;
+                self.call(function, &args, *span)
+            }
+            Expr::Index {
+                target,
+                index,
+                span,
+            } => {
+                let collection = self.evaluate(target)?;
+                let index = self.evaluate(index)?;
+                element(&collection, &index, *span)
+            }
+            Expr::Slice {
+                target,
+                start,
+                end,
+                span,
+            } => {
+                let collection = self.evaluate(target)?;
+                let mut bound = |bound: &Option<Box<Expr>>| match bound {
+                    Some(expr) => self.evaluate(expr).map(Some),
+                    None => Ok(None),
+                };
+                let (start, end) = (bound(start)?, bound(end)?);
+                slice(&collection, start, end, *span)
+            }
+            Expr::Member { target, name, span } => {
+                let object = self.evaluate(target)?;
+                field(&object, name, *span)
+            }
+        }
+    }
+}
+
+fn token(tok_type: TokenType, span: Span) -> Token<'static> {
+    Token {
+        tok_type,
+        tok_value: None,
+        span,
+        trivia: Vec::new(),
+    }
+}
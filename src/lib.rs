@@ -0,0 +1,33 @@
+pub mod ast;
+pub mod builtins;
+pub mod error;
+pub mod expr;
+pub mod interpreter;
+pub mod parser;
+pub mod token;
+pub mod tokenizer;
+pub mod value;
+
+#[cfg(test)]
+mod test_ast;
+#[cfg(test)]
+mod test_expr;
+#[cfg(test)]
+mod test_interpreter;
+#[cfg(test)]
+mod test_parser;
+
+pub use error::LetscriptError;
+
+/// The parser and interpreter grow the native stack by `STACK_GROWTH`
+/// whenever less than `STACK_RED_ZONE` is left, so deeply nested programs
+/// cannot overflow it.
+const STACK_RED_ZONE: usize = 128 * 1024;
+const STACK_GROWTH: usize = 4 * 1024 * 1024;
+
+/// Tokenizes, parses and executes a complete letscript program.
+pub fn run(program: &str) -> error::Result<()> {
+    let tokens = tokenizer::tokenize(program)?;
+    let program = parser::parse(&tokens)?;
+    interpreter::Interpreter::new().run(&program)
+}
@@ -1,29 +1,120 @@
-mod ast;
-mod expr;
-mod parser;
-mod test_ast;
-mod test_expr;
-mod test_parser;
-mod token;
-mod tokenizer;
-
+use interp::interpreter::Interpreter;
+use interp::parser::parse;
+use interp::token::TokenType;
+use interp::tokenizer::{is_unterminated, tokenize};
 use std::env;
+use std::fs;
+use std::io::{self, BufRead, IsTerminal, Read};
+use std::process;
+
+const USAGE: &str = "usage: interp [script.ls | -e <code> | -]";
 
-// use uwl::StringStream;
+enum Mode {
+    Script(String),
+    Eval(String),
+    Stdin,
+    Repl,
+    Help,
+}
 
-fn parse_args(args: Vec<String>) {}
+fn parse_args(args: Vec<String>) -> Result<Mode, String> {
+    let mut args = args.into_iter().skip(1);
+    let mode = match args.next().as_deref() {
+        None if io::stdin().is_terminal() => Mode::Repl,
+        None | Some("-") => Mode::Stdin,
+        Some("-e") => match args.next() {
+            Some(code) => Mode::Eval(code),
+            None => return Err(String::from("-e requires an argument")),
+        },
+        Some("-h" | "--help") => Mode::Help,
+        Some(flag) if flag.starts_with('-') => return Err(format!("unknown option '{}'", flag)),
+        Some(path) => Mode::Script(path.to_string()),
+    };
+    if let Some(extra) = args.next() {
+        return Err(format!("unexpected argument '{}'", extra));
+    }
+    Ok(mode)
+}
+
+/// Whether `source` leaves a bracket, brace, interpolation, block comment
+/// or raw string open, so the REPL should read more lines before running it.
+fn is_unfinished(source: &str) -> bool {
+    let tokens = match tokenize(source) {
+        Ok(tokens) => tokens,
+        Err(err) => return is_unterminated(&err),
+    };
+    let depth = tokens
+        .iter()
+        .fold(0i32, |depth, token| match token.tok_type {
+            TokenType::OpenParen | TokenType::OpenBracket | TokenType::OpenBrace => depth + 1,
+            TokenType::CloseParen | TokenType::CloseBracket | TokenType::CloseBrace => depth - 1,
+            _ => depth,
+        });
+    depth > 0
+}
+
+fn execute(interpreter: &mut Interpreter, source: &str) -> interp::error::Result<()> {
+    let tokens = tokenize(source)?;
+    let program = parse(&tokens)?;
+    interpreter.run(&program)
+}
+
+/// Runs input as it arrives, keeping variables and functions between
+/// entries. A line that leaves a bracket open continues on the next one.
+fn repl() {
+    let mut interpreter = Interpreter::new();
+    let mut input = String::new();
+    for line in io::stdin().lock().lines() {
+        match line {
+            Ok(line) => {
+                input.push_str(&line);
+                input.push('\n');
+                if is_unfinished(&input) {
+                    continue;
+                }
+                if let Err(err) = execute(&mut interpreter, &input) {
+                    eprintln!("{}", err.report("<stdin>", &input));
+                }
+                input.clear();
+            }
+            Err(err) => {
+                eprintln!("error reading stdin: {}", err);
+                process::exit(1);
+            }
+        }
+    }
+    if let Err(err) = execute(&mut interpreter, &input) {
+        eprintln!("{}", err.report("<stdin>", &input));
+    }
+}
 
 fn main() {
-    let args: Vec<String> = env::args().collect();
-    println!("Hello, world!");
-    loop {
-        let mut input = String::new();
-        std::io::stdin().read_line(&mut input).unwrap();
-        println!("{}", input);
-        let mut tokens = tokenizer::tokenize(&mut input);
-        let pasrsed_tokens = parser::parse(&mut tokens);
-        println!("{:?}", tokens.len());
-        //let ast = parser::parse(&mut tokens);
-        //println!("{:?}", ast);
+    let mode = parse_args(env::args().collect()).unwrap_or_else(|msg| {
+        eprintln!("{}\n{}", msg, USAGE);
+        process::exit(2);
+    });
+    let (name, program) = match mode {
+        Mode::Repl => return repl(),
+        Mode::Help => return println!("{}", USAGE),
+        Mode::Eval(code) => (String::from("<eval>"), code),
+        Mode::Script(path) => match fs::read_to_string(&path) {
+            Ok(program) => (path, program),
+            Err(err) => {
+                eprintln!("could not read {}: {}", path, err);
+                process::exit(1);
+            }
+        },
+        Mode::Stdin => {
+            let mut program = String::new();
+            if let Err(err) = io::stdin().read_to_string(&mut program) {
+                eprintln!("error reading stdin: {}", err);
+                process::exit(1);
+            }
+            (String::from("<stdin>"), program)
+        }
+    };
+    if let Err(err) = interp::run(&program) {
+        eprintln!("{}", err.report(&name, &program));
+        process::exit(1);
     }
 }
@@ -1,643 +1,1001 @@
-//use crate::ast::token_to_operable;
+use crate::error::{LetscriptError, Result};
 
-use crate::expr::operate;
-use crate::expr::operate_unary;
-
-use crate::token;
+use crate::token::Span;
 use crate::token::Token;
 use crate::token::TokenType;
-use crate::token::TokenValue;
 
-use crate::ast;
+use crate::ast::{Block, Expr, Program, Stmt};
+use crate::value::Value;
+use crate::{STACK_GROWTH, STACK_RED_ZONE};
+use num_bigint::BigInt;
 
-use std::collections::BTreeMap;
-use std::collections::HashMap;
-use std::collections::LinkedList;
 use std::iter::Peekable;
+use std::rc::Rc;
 use std::slice::Iter;
-use std::vec;
-
-pub fn parse(tokens: &mut Vec<Token>) {
-    let mut itr = tokens.iter();
-    let mut token = itr.next();
-
-    match token.unwrap().tok_type {
-        TokenType::Assign => parse_assignment_iter(Box::new(itr.peekable())),
-        TokenType::Bool => todo!(),
-        TokenType::Function => todo!(),
-        TokenType::Quote => todo!(),
-        TokenType::If => todo!(),
-        TokenType::Else => todo!(),
-        TokenType::While => todo!(),
-        TokenType::For => todo!(),
-        TokenType::Loop => todo!(),
-        TokenType::Object => todo!(),
-        TokenType::OpenBrace => todo!(),
-        TokenType::CloseBrace => todo!(),
-        TokenType::OpenBracket => todo!(),
-        TokenType::CloseBracket => todo!(),
-        TokenType::OpenParen => todo!(),
-        TokenType::CloseParen => todo!(),
-        TokenType::NewLine => todo!(),
-        TokenType::TokenError => todo!(),
-        _ => {}
-    }
+
+type TokenIter<'a> = Peekable<Iter<'a, Token<'a>>>;
+
+/// Whether a binary operator groups to the left, `a - b - c` being
+/// `(a - b) - c`, or to the right, `a ** b ** c` being `a ** (b ** c)`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Associativity {
+    Left,
+    Right,
 }
 
-pub fn parse_assignment(tokens: &mut Vec<Token>) {
-    match (tokens[0].tok_type, tokens[1].tok_type, tokens[2].tok_type) {
-        (TokenType::Assign, TokenType::Literal, TokenType::Is) => {
-            let name = tokens[1].tok_value.clone().unwrap().s_val.unwrap();
-            let value = parse_expression(&mut tokens[3..].to_vec());
-            ast::CALL_STACK.add_symbol(name, value);
-            println!("{:?}", ast::CALL_STACK)
+/// Binding power and associativity of every binary operator, loosest
+/// first. Operators with higher power bind tighter.
+static BINARY_OPERATORS: [(TokenType, u8, Associativity); 16] = [
+    (TokenType::Or, 1, Associativity::Left),
+    (TokenType::And, 2, Associativity::Left),
+    (TokenType::Equals, 3, Associativity::Left),
+    (TokenType::NotEqual, 3, Associativity::Left),
+    (TokenType::Is, 3, Associativity::Left),
+    (TokenType::In, 4, Associativity::Left),
+    (TokenType::GreaterThan, 4, Associativity::Left),
+    (TokenType::LessThan, 4, Associativity::Left),
+    (TokenType::Gte, 4, Associativity::Left),
+    (TokenType::Lte, 4, Associativity::Left),
+    (TokenType::Plus, 5, Associativity::Left),
+    (TokenType::Minus, 5, Associativity::Left),
+    (TokenType::Mult, 6, Associativity::Left),
+    (TokenType::Div, 6, Associativity::Left),
+    (TokenType::Mod, 6, Associativity::Left),
+    (TokenType::Pow, 8, Associativity::Right),
+];
+
+/// Binding power of prefix `-`: tighter than every binary operator except
+/// `**`, so `-x * 2` is `(-x) * 2` and `-x ** 2` is `-(x ** 2)`. Calls,
+/// indexing and member access bind tighter still.
+const NEGATION_POWER: u8 = 7;
+
+/// Binding power of `not`: its operand takes in everything that binds
+/// tighter than `and`, so `not a = b` is `not (a = b)` and `not a and b`
+/// is `(not a) and b`.
+const NOT_POWER: u8 = 2;
+
+/// Parses every statement in `tokens` into a [`Program`].
+pub fn parse(tokens: &[Token]) -> Result<Program> {
+    parse_program(&mut tokens.iter().peekable()).map_err(|err| match (err, tokens.last()) {
+        (LetscriptError::Parse { message, span }, Some(last)) if span == END_OF_INPUT => {
+            LetscriptError::parse(last.span, message)
         }
-        (_, _, _) => panic!("keyword cannot be used as a variable name"),
+        (err, _) => err,
+    })
+}
+
+/// Stands in for the span of errors at the end of the program, which
+/// [`parse`] replaces with the span of the last token.
+const END_OF_INPUT: Span = Span {
+    line: 0,
+    column: 0,
+    start: 0,
+    end: 0,
+};
+
+fn parse_program(tokens: &mut TokenIter) -> Result<Program> {
+    let mut body = Vec::new();
+    loop {
+        skip_newlines(tokens);
+        if tokens.peek().is_none() {
+            break;
+        }
+        body.push(parse_statement(tokens)?);
+        end_statement(tokens)?;
     }
+    Ok(Program { body })
 }
 
-pub fn parse_block(tokens: Vec<Token>) -> ast::SymbolType {
-    let mut block = Vec::<Token>::new();
-    let mut token = tokens.iter();
-    let mut current = token.next();
-    //while token.is_some() {}
+fn skip_newlines(tokens: &mut TokenIter) {
+    while tokens
+        .next_if(|t| t.tok_type == TokenType::NewLine)
+        .is_some()
+    {}
+}
 
-    return ast::SymbolType::Number("0".to_string());
+/// A statement must be followed by a newline, the end of its block or the
+/// end of the program.
+fn end_statement(tokens: &mut TokenIter) -> Result<()> {
+    match tokens.peek() {
+        None => Ok(()),
+        Some(t) if matches!(t.tok_type, TokenType::NewLine | TokenType::CloseBrace) => Ok(()),
+        Some(t) => Err(LetscriptError::parse(
+            t.span,
+            format!("expected the end of the statement, found {}", describe(t)),
+        )),
+    }
 }
 
-fn parse_assignment_iter<'a>(mut tokens:  Box<Peekable<Iter<'a, Token>>>) {
-    let mut tok = tokens.next().unwrap();
-    if tok.tok_type != TokenType::Literal {
-        panic!("invalid assignment syntax. must be in form 'var <name> is <value>");
+fn describe(token: &Token) -> String {
+    match token.tok_value.as_ref().and_then(|v| v.s_val.as_ref()) {
+        Some(text)
+            if matches!(
+                token.tok_type,
+                TokenType::Str
+                    | TokenType::TemplateHead
+                    | TokenType::TemplateMiddle
+                    | TokenType::TemplateTail
+            ) =>
+        {
+            format!("string {:?}", text)
+        }
+        Some(text) => format!("'{}'", text),
+        None => match token.tok_type {
+            TokenType::NewLine | TokenType::TokenError => token.tok_type.to_string(),
+            tok_type => format!("'{}'", tok_type),
+        },
     }
-    let name = tok.clone().tok_value.unwrap().s_val.unwrap();
-    tok = tokens.next().unwrap();
-    if tok.tok_type != TokenType::Is {
-        panic!("invalid assignment syntax. must be in form 'var <name> is <value>")
+}
+
+fn expect<'a>(
+    tokens: &mut TokenIter<'a>,
+    tok_type: TokenType,
+    what: &str,
+) -> Result<&'a Token<'a>> {
+    match tokens.peek() {
+        Some(t) if t.tok_type == tok_type => Ok(tokens.next().unwrap()),
+        Some(t) => Err(LetscriptError::parse(
+            t.span,
+            format!("expected {}, found {}", what, describe(t)),
+        )),
+        None => Err(LetscriptError::parse(
+            END_OF_INPUT,
+            format!("expected {}, found the end of the program", what),
+        )),
     }
-    let(value, _) = parse_expression_iter(tokens); 
-    ast::CALL_STACK.add_symbol(name, value);
-            println!("{:?}", ast::CALL_STACK)
-    
-}
-
-fn parse_expression(expr: &mut Vec<Token>) -> ast::SymbolType {
-    match expr[0].tok_type {
-        //TokenType::OpenBracket => {}
-        TokenType::Literal | TokenType::Minus | TokenType::Not => {
-            if expr[expr.len() - 1].tok_type == TokenType::NewLine {
-                expr.pop();
+}
+
+fn parse_statement(tokens: &mut TokenIter) -> Result<Stmt> {
+    stacker::maybe_grow(STACK_RED_ZONE, STACK_GROWTH, || {
+        let Some(&token) = tokens.peek() else {
+            return Err(LetscriptError::parse(
+                END_OF_INPUT,
+                "expected a statement, found the end of the program",
+            ));
+        };
+        match token.tok_type {
+            TokenType::Assign => parse_var_decl(tokens),
+            TokenType::OpenBrace => Ok(Stmt::Block(parse_block(tokens)?)),
+            TokenType::If => parse_if(tokens),
+            TokenType::While | TokenType::Loop | TokenType::For | TokenType::Each => {
+                parse_loop_statement(tokens, None)
             }
-            let resolved_vars = resolve_symbols(expr.clone().to_vec());
-            println!("{:?}", resolved_vars);
-            let non_unary = resolve_unary_operators(resolved_vars.clone().to_vec());
-            let postfix = infix_to_postfix(non_unary.clone());
-            let evaluated = eval_expression(&mut postfix.clone());
-            return ast::SymbolType::Number(evaluated.tok_value.unwrap().s_val.unwrap());
+            TokenType::Identifier if is_label(tokens) => parse_labelled_loop(tokens),
+            TokenType::Break | TokenType::Continue => parse_loop_control(tokens),
+            TokenType::Function if !is_function_expression(tokens) => parse_function(tokens),
+            TokenType::Return => parse_return(tokens),
+            TokenType::Else => Err(LetscriptError::parse(
+                token.span,
+                "'else' without a matching 'if'",
+            )),
+            TokenType::CloseBrace
+            | TokenType::CloseBracket
+            | TokenType::CloseParen
+            | TokenType::TokenError => Err(LetscriptError::parse(
+                token.span,
+                format!("unexpected {}", describe(token)),
+            )),
+            _ => parse_expression_statement(tokens),
         }
-        TokenType::Quote => {
-            if expr[expr.len() - 1].tok_type == TokenType::NewLine {
-                expr.pop();
+    })
+}
+
+/// An expression statement of the form `place = value` assigns rather than
+/// compares.
+fn parse_expression_statement(tokens: &mut TokenIter) -> Result<Stmt> {
+    let Some(length) = assignment_target_length(tokens) else {
+        return Ok(Stmt::ExprStmt(parse_expression(tokens)?));
+    };
+    let target_tokens: Vec<Token> = tokens.clone().take(length).cloned().collect();
+    let mut target_tokens = target_tokens.iter().peekable();
+    let target = parse_expression(&mut target_tokens)?;
+    if target_tokens.next().is_some()
+        || !matches!(
+            target,
+            Expr::Variable { .. } | Expr::Index { .. } | Expr::Member { .. }
+        )
+    {
+        // Something like `(a = b) and c = d` is a comparison.
+        return Ok(Stmt::ExprStmt(parse_expression(tokens)?));
+    }
+    let equals = tokens.nth(length).unwrap();
+    if matches!(
+        tokens.peek(),
+        None | Some(Token {
+            tok_type: TokenType::NewLine,
+            ..
+        })
+    ) {
+        return Err(LetscriptError::parse(
+            equals.span,
+            "expected a value after '='",
+        ));
+    }
+    let value = parse_expression(tokens)?;
+    Ok(Stmt::Assign {
+        span: target.span().to(value.span()),
+        target,
+        value,
+    })
+}
+
+/// Counts the tokens before a top-level `=` in the current statement.
+fn assignment_target_length(tokens: &TokenIter) -> Option<usize> {
+    let mut depth = 0;
+    let mut previous = None;
+    for (i, token) in tokens.clone().enumerate() {
+        match token.tok_type {
+            TokenType::OpenParen
+            | TokenType::OpenBracket
+            | TokenType::OpenBrace
+            | TokenType::TemplateHead => depth += 1,
+            TokenType::CloseParen
+            | TokenType::CloseBracket
+            | TokenType::CloseBrace
+            | TokenType::TemplateTail => {
+                if depth == 0 {
+                    return None;
+                }
+                depth -= 1;
             }
-            return parse_string(expr.clone().to_vec());
-        }
-        TokenType::OpenBracket => {
-            return parse_array(expr.clone().to_vec());
-        }
-        TokenType::OpenBrace => {
-            return parse_object(expr.clone().to_vec());
+            TokenType::NewLine | TokenType::Comma if depth == 0 => return None,
+            TokenType::Equals if depth == 0 && i > 0 && previous != Some(TokenType::Not) => {
+                return Some(i)
+            }
+            _ => {}
         }
-        _ => panic!("invalid expression syntax"),
+        previous = Some(token.tok_type);
     }
+    None
 }
 
-fn parse_expression_iter<'a>(mut tokens:  Box<Peekable<Iter<'a, Token>>>) -> (ast::SymbolType, Box<Peekable<Iter<'a, Token>>>){
-
-    let mut tok = tokens.peek_mut();
-    let mut val: ast::SymbolType;
-    let mut toks:  Box<Peekable<Iter<'a, Token>>>;
-    match tok.unwrap().tok_type {
-        TokenType::Literal | TokenType::Minus | TokenType::Not => {
-            (val,toks) = parse_logical_iter(tokens);
-        }
-        TokenType::Quote => {
-            (val,toks) = parse_string_iter(tokens);
-        }
-        TokenType::OpenBracket => {
-            (val,toks) = parse_array_iter(tokens);
-        }
-        TokenType::OpenBrace => {
-          todo!("implement");
-        }
-        _ => panic!("invalid expression syntax"),
+fn parse_var_decl(tokens: &mut TokenIter) -> Result<Stmt> {
+    let keyword = tokens.next().unwrap();
+    let syntax_error = |span: Span| {
+        LetscriptError::parse(
+            span,
+            "invalid assignment syntax. must be in form 'var <name> is <value>'",
+        )
     };
-    return (val, toks)
-}
-
-pub fn parse_logical_iter<'a>(mut tokens:  Box<Peekable<Iter<'a, Token>>>) -> (ast::SymbolType, Box<Peekable<Iter<'a, Token>>>){
-
-    let operators = vec![
-        TokenType::Literal,
-        TokenType::OpenParen,
-        TokenType::CloseParen,
-        TokenType::Plus,
-        TokenType::Minus,
-        TokenType::Bool,
-        TokenType::Mult,
-        TokenType::Mod,
-        TokenType::Div,
-        TokenType::And,
-        TokenType::Or,
-        TokenType::Not,
-        TokenType::Equals,
-        TokenType::NotEqual,
-        TokenType::GreaterThan,
-        TokenType::LessThan,
-        TokenType::Gte,
-        TokenType::Lte,
-        TokenType::In];
-    let mut tok = tokens.next();
-    let mut vc = Vec::<Token>::new();
-    while tok.is_some() {
-        let i =  tok.unwrap();
-        if operators.contains(&i.tok_type) {
-            vc.push(i.clone())
-        }
-        tok = tokens.next();
+    let name_tok = tokens.next().ok_or_else(|| syntax_error(keyword.span))?;
+    let name = parse_name(name_tok)?;
+    let is_tok = tokens.next().ok_or_else(|| syntax_error(name_tok.span))?;
+    if is_tok.tok_type != TokenType::Is {
+        return Err(syntax_error(is_tok.span));
     }
-    let resolved_vars = resolve_symbols(vc);
-    println!("{:?}", resolved_vars);
-    let non_unary = resolve_unary_operators(resolved_vars.clone().to_vec());
-    let postfix = infix_to_postfix(non_unary.clone());
-    let evaluated = eval_expression(&mut postfix.clone());
-    return (ast::SymbolType::Number(evaluated.tok_value.unwrap().s_val.unwrap()), tokens);
-
-}
-
-fn parse_string_iter<'a>(mut tokens: Box<Peekable<Iter<'a, Token>>>) -> (ast::SymbolType,Box<Peekable<Iter<'a, Token>>>) {
-    let t1 = tokens.next().unwrap();
-    let t2 = tokens.next().unwrap();
-    let t3 = tokens.next().unwrap(); 
-  
-    let val = match (t1.tok_type, t2.tok_type, t3.tok_type) {
-        ( TokenType::Quote, TokenType::Literal, TokenType::Quote) => {
-            ast::SymbolType::String(t2.clone().tok_value.unwrap().s_val.unwrap())
-        }
-        _ => {panic!("not a valid string")}
-        };
-    return (val, tokens)
+    if matches!(
+        tokens.peek(),
+        None | Some(Token {
+            tok_type: TokenType::NewLine,
+            ..
+        })
+    ) {
+        return Err(LetscriptError::parse(
+            is_tok.span,
+            "expected a value after 'is'",
+        ));
+    }
+    let value = parse_expression(tokens)?;
+    Ok(Stmt::VarDecl {
+        name,
+        span: keyword.span.to(value.span()),
+        value,
+    })
 }
 
-fn parse_array_iter<'a>(mut tokens:Box<Peekable<Iter<'a, Token>>>) -> (ast::SymbolType,  Box<Peekable<Iter<'a, Token>>>) {
-    let mut symbols = Vec::<ast::SymbolType>::new();
-    let mut tok = tokens.next();
+/// Reads a variable, parameter or field name.
+fn parse_name(token: &Token) -> Result<String> {
+    match token.tok_type {
+        TokenType::Identifier => literal_text(token),
+        TokenType::Literal => Err(LetscriptError::parse(
+            token.span,
+            format!("{} is not a valid name", describe(token)),
+        )),
+        _ => Err(LetscriptError::parse(
+            token.span,
+            "keyword cannot be used as a variable name",
+        )),
+    }
+}
 
-    while tok.is_some() {
-        let mut i = tok.unwrap();
-        match i.tok_type {
-            TokenType::OpenBracket => {
-                let mut arr;
-               ( arr ,_) = parse_array_iter(tokens.clone());
-                symbols.push(arr);
-            }
-            TokenType::CloseBracket => break,
-            TokenType::Literal => {
-                let existing_var = ast::CALL_STACK
-                    .lookup_symbol(i.clone().tok_value.unwrap().s_val.unwrap());
-                if existing_var.is_some() {
-                    symbols.push(match existing_var.unwrap() {
-                        _ => ast::SymbolType::Pointer(
-                            i.clone().tok_value.unwrap().s_val.unwrap(),
-                        ),
-                    });
-                } else {
-                    symbols.push(ast::SymbolType::Number(
-                        i.clone().tok_value.unwrap().s_val.unwrap(),
-                    ));
-                }
+fn parse_block(tokens: &mut TokenIter) -> Result<Block> {
+    let open = expect(tokens, TokenType::OpenBrace, "'{'")?;
+    let mut body = Vec::new();
+    loop {
+        skip_newlines(tokens);
+        match tokens.peek() {
+            None => return Err(LetscriptError::parse(open.span, "unclosed '{'")),
+            Some(t) if t.tok_type == TokenType::CloseBrace => {
+                let close = tokens.next().unwrap();
+                return Ok(Block {
+                    body,
+                    span: open.span.to(close.span),
+                });
             }
-            TokenType::Comma => {}
-            TokenType::Quote => {
-                println!("string: {:?}", i);
-                let mut str;
-                (str,_) = parse_string_iter(tokens.clone());
-                symbols.push(str);
+            Some(_) => {
+                body.push(parse_statement(tokens)?);
+                end_statement(tokens)?;
             }
-            _ => panic!("invalid array syntax"),
         }
-
-        tok = tokens.next(); // Regular increment.
     }
-    return(ast::SymbolType::Array(symbols), tokens);
 }
 
-fn parse_string(tokens: Vec<Token>) -> ast::SymbolType {
-    if tokens.len() < 3 {
-        panic!("invalid string syntax");
+fn parse_if(tokens: &mut TokenIter) -> Result<Stmt> {
+    let keyword = tokens.next().unwrap();
+    let condition = parse_expression(tokens)?;
+    let then_branch = parse_block(tokens)?;
+    let mut span = keyword.span.to(then_branch.span);
+
+    // `else` may start the line after the closing brace.
+    let mut lookahead = tokens.clone();
+    skip_newlines(&mut lookahead);
+    let else_branch = match lookahead.peek() {
+        Some(t) if t.tok_type == TokenType::Else => {
+            *tokens = lookahead;
+            tokens.next();
+            let block = match tokens.peek() {
+                Some(t) if t.tok_type == TokenType::If => {
+                    let nested = parse_if(tokens)?;
+                    Block {
+                        span: nested.span(),
+                        body: vec![nested],
+                    }
+                }
+                _ => parse_block(tokens)?,
+            };
+            span = span.to(block.span);
+            Some(block)
+        }
+        _ => None,
+    };
+    Ok(Stmt::If {
+        condition,
+        then_branch,
+        else_branch,
+        span,
+    })
+}
+
+/// Whether `function` is directly followed by its parameters rather than
+/// a name.
+fn is_function_expression(tokens: &TokenIter) -> bool {
+    let mut lookahead = tokens.clone();
+    lookahead.next();
+    matches!(lookahead.next(), Some(t) if t.tok_type == TokenType::OpenParen)
+}
+
+/// Whether the statement starts with `name:`.
+fn is_label(tokens: &TokenIter) -> bool {
+    let mut lookahead = tokens.clone();
+    lookahead.next();
+    matches!(lookahead.next(), Some(t) if t.tok_type == TokenType::Colon)
+}
+
+/// `name: <loop>`
+fn parse_labelled_loop(tokens: &mut TokenIter) -> Result<Stmt> {
+    let name_tok = tokens.next().unwrap();
+    let label = parse_name(name_tok)?;
+    tokens.next();
+    match tokens.peek() {
+        Some(t)
+            if matches!(
+                t.tok_type,
+                TokenType::While | TokenType::Loop | TokenType::For | TokenType::Each
+            ) =>
+        {
+            parse_loop_statement(tokens, Some(label))
+        }
+        _ => Err(LetscriptError::parse(
+            name_tok.span,
+            format!("label '{}' must be followed by a loop", label),
+        )),
     }
-    let mut itr = tokens.iter();
-    let mut token = itr.next();
-    println!("{:?}", tokens);
-    if token.unwrap().tok_type != TokenType::Quote {
-        panic!("invalid string syntax 2");
+}
+
+fn parse_loop_statement(tokens: &mut TokenIter, label: Option<String>) -> Result<Stmt> {
+    match tokens.peek().unwrap().tok_type {
+        TokenType::While => parse_while(tokens, label),
+        TokenType::Loop => parse_loop(tokens, label),
+        TokenType::For => parse_for(tokens, label),
+        _ => parse_each(tokens, label),
     }
-    println!("before loop");
-    token = itr.next();
-    let mut value = "".to_string();
-    while token.is_some() {
-        println!("parse string: {:?}", token.clone().unwrap().tok_type);
-        match token.clone().unwrap().tok_type {
-            TokenType::Literal => {
-                value = token
-                    .unwrap()
-                    .clone()
-                    .tok_value
-                    .unwrap()
-                    .s_val
-                    .unwrap()
-                    .clone();
+}
+
+/// `break` or `continue`, optionally followed by a loop label.
+fn parse_loop_control(tokens: &mut TokenIter) -> Result<Stmt> {
+    let keyword = tokens.next().unwrap();
+    let (label, span) = match tokens.peek() {
+        Some(t) if t.tok_type == TokenType::Identifier => {
+            let label_tok = tokens.next().unwrap();
+            (
+                Some(parse_name(label_tok)?),
+                keyword.span.to(label_tok.span),
+            )
+        }
+        _ => (None, keyword.span),
+    };
+    Ok(match keyword.tok_type {
+        TokenType::Break => Stmt::Break { label, span },
+        _ => Stmt::Continue { label, span },
+    })
+}
+
+fn parse_while(tokens: &mut TokenIter, label: Option<String>) -> Result<Stmt> {
+    let keyword = tokens.next().unwrap();
+    let condition = parse_expression(tokens)?;
+    let body = parse_block(tokens)?;
+    Ok(Stmt::While {
+        label,
+        condition,
+        span: keyword.span.to(body.span),
+        body,
+    })
+}
+
+fn parse_loop(tokens: &mut TokenIter, label: Option<String>) -> Result<Stmt> {
+    let keyword = tokens.next().unwrap();
+    let body = parse_block(tokens)?;
+    Ok(Stmt::Loop {
+        label,
+        span: keyword.span.to(body.span),
+        body,
+    })
+}
+
+/// `for (init, condition, step) { ... }`
+fn parse_for(tokens: &mut TokenIter, label: Option<String>) -> Result<Stmt> {
+    let keyword = tokens.next().unwrap();
+    expect(tokens, TokenType::OpenParen, "'(' after 'for'")?;
+    let init = parse_statement(tokens)?;
+    expect(tokens, TokenType::Comma, "',' after the loop initializer")?;
+    let condition = parse_expression(tokens)?;
+    expect(tokens, TokenType::Comma, "',' after the loop condition")?;
+    let step = parse_statement(tokens)?;
+    expect(tokens, TokenType::CloseParen, "')'")?;
+    let body = parse_block(tokens)?;
+    Ok(Stmt::For {
+        label,
+        init: Box::new(init),
+        condition,
+        step: Box::new(step),
+        span: keyword.span.to(body.span),
+        body,
+    })
+}
+
+fn parse_each(tokens: &mut TokenIter, label: Option<String>) -> Result<Stmt> {
+    let keyword = tokens.next().unwrap();
+    let mut names = Vec::new();
+    loop {
+        match tokens.next() {
+            Some(t) => names.push(parse_name(t)?),
+            None => {
+                return Err(LetscriptError::parse(
+                    keyword.span,
+                    "expected a loop variable",
+                ))
             }
-            TokenType::Quote => break,
-            _ => panic!("invalid string syntax 3"),
         }
-        token = itr.next();
+        if names.len() == 2 || tokens.next_if(|t| t.tok_type == TokenType::Comma).is_none() {
+            break;
+        }
     }
-    println!("after loop");
-    ast::SymbolType::String(value)
+    expect(tokens, TokenType::In, "'in'")?;
+    let item = names.pop().unwrap();
+    let key = names.pop();
+    let collection = parse_expression(tokens)?;
+    let body = parse_block(tokens)?;
+    Ok(Stmt::Each {
+        label,
+        key,
+        item,
+        collection,
+        span: keyword.span.to(body.span),
+        body,
+    })
+}
+
+/// `function name(params) { ... }`
+fn parse_function(tokens: &mut TokenIter) -> Result<Stmt> {
+    let keyword = tokens.next().unwrap();
+    let name = match tokens.next() {
+        Some(t) => parse_name(t)?,
+        None => {
+            return Err(LetscriptError::parse(
+                keyword.span,
+                "expected a function name",
+            ))
+        }
+    };
+    let (params, body) = parse_function_rest(keyword, tokens)?;
+    Ok(Stmt::FunctionDecl {
+        name,
+        params,
+        span: keyword.span.to(body.span),
+        body: Rc::new(body),
+    })
 }
 
-fn parse_array(tokens: Vec<Token>) -> ast::SymbolType {
-    let mut symbols = Vec::<ast::SymbolType>::new();
-    let mut i = 1;
+/// `function (params) { ... }` used as a value.
+fn parse_function_expression(keyword: &Token, tokens: &mut TokenIter) -> Result<Expr> {
+    let (params, body) = parse_function_rest(keyword, tokens)?;
+    Ok(Expr::Function {
+        params,
+        span: keyword.span.to(body.span),
+        body: Rc::new(body),
+    })
+}
 
-    while i < tokens.len() - 1 {
-        match tokens[i].tok_type {
-            TokenType::OpenBracket => {
-                let nested = tokens[i..].to_vec();
-                symbols.push(parse_array(nested));
+/// Parses the parameter list and body following `function` or its name.
+fn parse_function_rest(keyword: &Token, tokens: &mut TokenIter) -> Result<(Vec<String>, Block)> {
+    expect(tokens, TokenType::OpenParen, "'(' before the parameters")?;
+    let mut params = Vec::new();
+    if tokens
+        .next_if(|t| t.tok_type == TokenType::CloseParen)
+        .is_none()
+    {
+        loop {
+            match tokens.next() {
+                Some(t) => params.push(parse_name(t)?),
+                None => return Err(LetscriptError::parse(keyword.span, "unclosed '('")),
             }
-            TokenType::CloseBracket => break,
-            TokenType::Literal => {
-                let existing_var = ast::CALL_STACK
-                    .lookup_symbol(tokens[i].clone().tok_value.unwrap().s_val.unwrap());
-                if existing_var.is_some() {
-                    symbols.push(match existing_var.unwrap() {
-                        _ => ast::SymbolType::Pointer(
-                            tokens[i].clone().tok_value.unwrap().s_val.unwrap(),
-                        ),
-                    });
-                } else {
-                    symbols.push(ast::SymbolType::Number(
-                        tokens[i].clone().tok_value.unwrap().s_val.unwrap(),
-                    ));
+            match tokens.next() {
+                Some(t) if t.tok_type == TokenType::Comma => {}
+                Some(t) if t.tok_type == TokenType::CloseParen => break,
+                Some(t) => {
+                    return Err(LetscriptError::parse(
+                        t.span,
+                        format!("expected ',' or ')', found {}", describe(t)),
+                    ))
                 }
+                None => return Err(LetscriptError::parse(keyword.span, "unclosed '('")),
             }
-            TokenType::Comma => {}
-            TokenType::Quote => {
-                println!("string: {:?}", tokens[i]);
-                let str_slice = tokens[i..i + 3].to_vec();
-                symbols.push(parse_string(str_slice));
-                i += 2; // added 2 instead of 3 because at the end of the loop we'll also increment by 1.
-            }
-            _ => panic!("invalid array syntax"),
         }
+    }
+    Ok((params, parse_block(tokens)?))
+}
 
-        i += 1; // Regular increment.
+fn parse_return(tokens: &mut TokenIter) -> Result<Stmt> {
+    let keyword = tokens.next().unwrap();
+    match tokens.peek() {
+        None => Ok(Stmt::Return {
+            value: None,
+            span: keyword.span,
+        }),
+        Some(t) if matches!(t.tok_type, TokenType::NewLine | TokenType::CloseBrace) => {
+            Ok(Stmt::Return {
+                value: None,
+                span: keyword.span,
+            })
+        }
+        Some(_) => {
+            let value = parse_expression(tokens)?;
+            Ok(Stmt::Return {
+                span: keyword.span.to(value.span()),
+                value: Some(value),
+            })
+        }
     }
+}
 
-    ast::SymbolType::Array(symbols)
+fn literal_text(token: &Token) -> Result<String> {
+    match token.tok_value.as_ref().and_then(|v| v.s_val.as_deref()) {
+        Some(text) => Ok(text.to_string()),
+        None => Err(LetscriptError::parse(
+            token.span,
+            format!("expected a literal, found {}", describe(token)),
+        )),
+    }
 }
 
-fn parse_object(tokens: Vec<Token>) -> ast::SymbolType {
-    if tokens.len() < 5 {
-        panic!("invalid object syntax");
+/// Converts a number, boolean, null or undefined literal into its value.
+pub fn literal_value(token: &Token) -> Result<Value> {
+    let text = literal_text(token)?;
+    match text.as_str() {
+        "true" => Ok(Value::Bool(true)),
+        "false" => Ok(Value::Bool(false)),
+        "null" => Ok(Value::Null),
+        "undefined" => Ok(Value::Undefined),
+        _ => number_value(&text, token.span),
     }
-    let mut token_iter = tokens.iter().peekable();
-    let mut token = token_iter.next().unwrap();
-    let mut object_map = BTreeMap::<ast::SymbolType, ast::SymbolType>::new();
-    if token.tok_type != TokenType::OpenBrace {
-        panic!("Objects must start with an open brace");
+}
+
+/// Converts the text of a number literal, as accepted by the tokenizer,
+/// into an int or a float. Integers too large for an `i64` become big
+/// integers; floats that do not fit are an error.
+fn number_value(text: &str, span: Span) -> Result<Value> {
+    let invalid = || LetscriptError::parse(span, format!("'{}' is not a valid number", text));
+    let digits = text.replace('_', "");
+    let radix = match digits.get(..2) {
+        Some("0x" | "0X") => 16,
+        Some("0o" | "0O") => 8,
+        Some("0b" | "0B") => 2,
+        _ => 10,
+    };
+    if radix == 10 && digits.contains(['.', 'e', 'E']) {
+        let x: f64 = digits.parse().map_err(|_| invalid())?;
+        if x.is_infinite() {
+            return Err(LetscriptError::parse(
+                span,
+                format!("float {} is out of range", text),
+            ));
+        }
+        return Ok(Value::Float(x));
     }
-    //token = token_iter.next().unwrap();
-    while token.tok_type != TokenType::CloseBrace || token.tok_type != TokenType::NewLine {
-        let key_iter = token_iter
-            .by_ref()
-            .take_while(|t| t.tok_type != TokenType::Colon)
-            .map(|t| t.clone());
-
-        let mut key = key_iter.collect::<Vec<Token>>();
-
-        let key_symbol = parse_expression(&mut key);
-        token = token_iter.peek().unwrap();
-        println!("token after key: {:?}", token.tok_type);
-        // if token.tok_type != TokenType::Colon {
-        //     panic!("Objects must have a colon after the key");
-        // }
-        let mut value = token_iter
-            .by_ref()
-            .take_while(|t| !(vec![TokenType::Comma, TokenType::CloseBrace].contains(&t.tok_type)))
-            .cloned()
-            .collect::<Vec<Token>>();
-        let value_symbol = parse_expression(&mut value);
-        token = token_iter.peek().unwrap();
-        println!("token after value: {:?}", token.tok_type);
-
-        object_map.insert(key_symbol, value_symbol);
+    let digits = if radix == 10 {
+        &digits[..]
+    } else {
+        &digits[2..]
+    };
+    let x = BigInt::parse_bytes(digits.as_bytes(), radix).ok_or_else(invalid)?;
+    Ok(Value::from_big(x))
+}
+
+/// Parses one expression, stopping at the first token that cannot
+/// continue it.
+pub fn parse_expression(tokens: &mut TokenIter) -> Result<Expr> {
+    parse_binary(tokens, 0, None)
+}
+
+/// Parses an operand followed by the binary operators binding tighter than
+/// `min_power`. `after` is the operator the expression is the right operand
+/// of, if any.
+fn parse_binary(tokens: &mut TokenIter, min_power: u8, after: Option<&Token>) -> Result<Expr> {
+    let mut left = parse_unary(tokens, after)?;
+    while let Some((operator, power, associativity)) = binary_operator(tokens) {
+        if power <= min_power {
+            break;
+        }
+        if operator.tok_type == TokenType::NotEqual {
+            tokens.next();
+        }
+        tokens.next();
+        let right_power = match associativity {
+            Associativity::Left => power,
+            Associativity::Right => power - 1,
+        };
+        let right = stacker::maybe_grow(STACK_RED_ZONE, STACK_GROWTH, || {
+            parse_binary(tokens, right_power, Some(&operator))
+        })?;
+        left = Expr::Binary {
+            op: operator.tok_type,
+            span: left.span().to(right.span()),
+            left: Box::new(left),
+            right: Box::new(right),
+        };
     }
+    Ok(left)
+}
 
-    return ast::SymbolType::Object(object_map);
-}
-
-pub fn resolve_symbols(tokens: Vec<Token>) -> Vec<Token> {
-    let mut new_tokens = Vec::<Token>::new();
-    for token in tokens {
-        match token.clone().tok_type {
-            TokenType::Literal => {
-                let symbol =
-                    ast::CALL_STACK.lookup_symbol(token.clone().tok_value.unwrap().s_val.unwrap());
-                match symbol.clone() {
-                    Some(sym) => {
-                        new_tokens.push(Token {
-                            tok_type: TokenType::Literal,
-                            tok_value: Some(TokenValue {
-                                s_val: match symbol.clone().unwrap() {
-                                    ast::SymbolType::Number(s) => Some(s),
-                                    ast::SymbolType::String(s) => Some(s),
-                                    _ => panic!("invalid symbol type"),
-                                },
-                            }),
-                        });
-                    }
-                    None => {
-                        new_tokens.push(token.clone());
+/// The binary operator at the front of `tokens` with its binding power,
+/// merging `not =` into `NotEqual` and reading `==` as `Equals`.
+fn binary_operator(tokens: &TokenIter) -> Option<(Token<'static>, u8, Associativity)> {
+    let mut lookahead = tokens.clone();
+    let first = lookahead.next()?;
+    let (tok_type, span) = match (first.tok_type, lookahead.next()) {
+        (TokenType::Not, Some(t)) if t.tok_type == TokenType::Equals => {
+            (TokenType::NotEqual, first.span.to(t.span))
+        }
+        (TokenType::DoubleEquals, _) => (TokenType::Equals, first.span),
+        (tok_type, _) => (tok_type, first.span),
+    };
+    let (_, power, associativity) = BINARY_OPERATORS
+        .iter()
+        .find(|(operator, _, _)| *operator == tok_type)?;
+    let operator = Token {
+        tok_type,
+        tok_value: None,
+        span,
+        trivia: Vec::new(),
+    };
+    Some((operator, *power, *associativity))
+}
+
+/// Parses `-` or `not` applied to an operand, or an operand with its
+/// postfix operations. Negated number literals become literals.
+fn parse_unary(tokens: &mut TokenIter, after: Option<&Token>) -> Result<Expr> {
+    stacker::maybe_grow(STACK_RED_ZONE, STACK_GROWTH, || {
+        let Some(&token) = tokens.peek() else {
+            return Err(missing_operand(None, after));
+        };
+        match token.tok_type {
+            TokenType::Minus | TokenType::Not => {
+                tokens.next();
+                let power = match token.tok_type {
+                    TokenType::Not => NOT_POWER,
+                    _ => NEGATION_POWER,
+                };
+                let operand = parse_binary(tokens, power, Some(token))?;
+                let span = token.span.to(operand.span());
+                if let (TokenType::Minus, Expr::Literal { value, .. }) = (token.tok_type, &operand)
+                {
+                    if let Some(value) = negated(value) {
+                        return Ok(Expr::Literal { value, span });
                     }
                 }
+                Ok(Expr::Unary {
+                    op: token.tok_type,
+                    span,
+                    operand: Box::new(operand),
+                })
             }
-            _ => {
-                new_tokens.push(token);
+            _ if starts_operand(token) => {
+                tokens.next();
+                let primary = parse_primary(token, tokens)?;
+                parse_postfix(primary, tokens)
             }
+            _ => Err(missing_operand(Some(token), after)),
         }
+    })
+}
+
+fn negated(value: &Value) -> Option<Value> {
+    match value {
+        Value::Int(x) => Some(match x.checked_neg() {
+            Some(x) => Value::Int(x),
+            None => Value::from_big(-BigInt::from(*x)),
+        }),
+        Value::BigInt(x) => Some(Value::from_big(-x)),
+        Value::Float(x) => Some(Value::Float(-x)),
+        _ => None,
     }
-    return new_tokens;
-}
-
-pub fn collect_expression_tokens<'a>(
-    current_token: &'a Token,
-    tokens: &'a mut Iter<'a, Token>,
-) -> (Token, Vec<Token>) {
-    let possible_tokens = vec![
-        TokenType::OpenParen,
-        TokenType::CloseParen,
-        TokenType::Literal,
-        TokenType::Plus,
-        TokenType::Minus,
-    ];
-    let t = tokens;
-    let mut current = t.next().unwrap();
-    let mut acc: Vec<Token> = Vec::new();
-    while possible_tokens.contains(&current.tok_type) {
-        let val = current.tok_value.clone();
-        if val.is_some() {
-            println!("{:?}", val.unwrap().s_val.unwrap());
-        }
+}
+
+fn starts_operand(token: &Token) -> bool {
+    matches!(
+        token.tok_type,
+        TokenType::Literal
+            | TokenType::Identifier
+            | TokenType::Str
+            | TokenType::TemplateHead
+            | TokenType::OpenParen
+            | TokenType::OpenBracket
+            | TokenType::OpenBrace
+            | TokenType::Function
+    )
+}
 
-        acc.push(current.clone());
-        current = t.next().unwrap();
+/// The error for a missing operand where `found` is. An operator in its
+/// place is blamed first, then the operator expecting the operand.
+fn missing_operand(found: Option<&Token>, after: Option<&Token>) -> LetscriptError {
+    let is_operator = |t: &&Token| {
+        t.tok_type == TokenType::CloseParen
+            || BINARY_OPERATORS
+                .iter()
+                .any(|(operator, _, _)| *operator == t.tok_type)
+    };
+    match (found, after) {
+        (Some(t), _) if is_operator(&t) => {
+            LetscriptError::parse(t.span, "missing operand for operator")
+        }
+        (_, Some(t)) => LetscriptError::parse(t.span, "missing operand for operator"),
+        (Some(t), None) => LetscriptError::parse(
+            t.span,
+            format!("expected an expression, found {}", describe(t)),
+        ),
+        (None, None) => LetscriptError::parse(END_OF_INPUT, "expected an expression"),
     }
-    return (current.clone(), acc);
 }
 
-pub fn find_number_sign_helper(mut itr: std::vec::IntoIter<Token>) -> Token {
-    let mut token = itr.next();
-    while token.is_some()
-        && [TokenType::Plus, TokenType::Minus].contains(&token.clone().unwrap().tok_type)
-    {}
-    return Token {
-        tok_type: TokenType::TokenError,
-        tok_value: None,
+/// Parses `[index]` or `[start:end]` after `target`, the `[` having been
+/// consumed.
+fn parse_index(target: Expr, tokens: &mut TokenIter) -> Result<Expr> {
+    let bound = |tokens: &mut TokenIter, after: TokenType| match tokens.peek() {
+        Some(t) if t.tok_type == after => Ok(None),
+        _ => parse_expression(tokens).map(|expr| Some(Box::new(expr))),
     };
+    let start = bound(tokens, TokenType::Colon)?;
+    if tokens.next_if(|t| t.tok_type == TokenType::Colon).is_some() {
+        let end = bound(tokens, TokenType::CloseBracket)?;
+        let close = expect(tokens, TokenType::CloseBracket, "']'")?;
+        return Ok(Expr::Slice {
+            span: target.span().to(close.span),
+            target: Box::new(target),
+            start,
+            end,
+        });
+    }
+    let close = expect(tokens, TokenType::CloseBracket, "':' or ']'")?;
+    Ok(Expr::Index {
+        span: target.span().to(close.span),
+        target: Box::new(target),
+        // Without a colon the start bound was parsed.
+        index: start.unwrap(),
+    })
 }
 
-pub fn resolve_unary_operators(mut tokens: Vec<Token>) -> Vec<Token> {
-    let mut z = 0;
-    let mut new_tokens = Vec::<Option<Token>>::new();
-    let mut window = (
-        None,
-        None,
-        match tokens.get_mut(z) {
-            Some(token) => Some(token.tok_type),
-            None => None,
-        },
-    );
-    while z < tokens.len() {
-        match window {
-            (
-                Some(
-                    TokenType::Minus
-                    | TokenType::Plus
-                    | TokenType::Mult
-                    | TokenType::Div
-                    | TokenType::Mod,
-                )
-                | None,
-                Some(TokenType::Minus),
-                Some(TokenType::Literal),
-            ) => {
-                let mut val = String::from("-");
-                println!("{:?}", tokens[z].tok_type);
-                val.push_str(tokens[z].tok_value.clone().unwrap().s_val.unwrap().as_str());
-                new_tokens.pop();
-                new_tokens.push(None);
-                new_tokens.push(Some(Token {
-                    tok_type: TokenType::Literal,
-                    tok_value: Some(TokenValue { s_val: Some(val) }),
-                }))
+/// Applies the calls, indexing and member accesses following `target`.
+fn parse_postfix(mut target: Expr, tokens: &mut TokenIter) -> Result<Expr> {
+    while let Some(&token) = tokens.peek() {
+        target = match token.tok_type {
+            TokenType::OpenParen => {
+                tokens.next();
+                let (args, end) = parse_arguments(token, tokens)?;
+                Expr::Call {
+                    span: target.span().to(end),
+                    callee: Box::new(target),
+                    args,
+                }
             }
-            (_, Some(TokenType::Not), Some(TokenType::Literal)) => {
-                let val = tokens[z].clone();
-                let operator = tokens[z - 1].clone();
-                let negated = operate_unary(val, operator);
-                new_tokens.pop();
-                new_tokens.push(Some(negated));
+            TokenType::OpenBracket => {
+                tokens.next();
+                parse_index(target, tokens)?
             }
-            (_, Some(TokenType::Not), Some(TokenType::Equals)) => {
-                let negated = Token {
-                    tok_type: TokenType::NotEqual,
-                    tok_value: None,
+            TokenType::Period => {
+                tokens.next();
+                let Some(field) = tokens.next() else {
+                    return Err(LetscriptError::parse(token.span, "expected a field name"));
                 };
-                new_tokens.pop();
-                new_tokens.push(Some(negated));
+                Expr::Member {
+                    span: target.span().to(field.span),
+                    target: Box::new(target),
+                    name: parse_name(field)?,
+                }
             }
-            (_, _, _) => {
-                new_tokens.push(Some(tokens[z].clone()));
+            _ => return Ok(target),
+        };
+    }
+    Ok(target)
+}
+
+fn parse_primary(token: &Token, tokens: &mut TokenIter) -> Result<Expr> {
+    match token.tok_type {
+        TokenType::Literal => Ok(Expr::Literal {
+            value: literal_value(token)?,
+            span: token.span,
+        }),
+        TokenType::Identifier => Ok(Expr::Variable {
+            name: literal_text(token)?,
+            span: token.span,
+        }),
+        TokenType::Str => string_literal(token),
+        TokenType::TemplateHead => parse_template(token, tokens),
+        TokenType::OpenParen => {
+            let inner = parse_expression(tokens)?;
+            match tokens.next() {
+                Some(t) if t.tok_type == TokenType::CloseParen => Ok(inner),
+                Some(t) => Err(LetscriptError::parse(
+                    t.span,
+                    format!("expected ')', found {}", describe(t)),
+                )),
+                None => Err(LetscriptError::parse(token.span, "unclosed '('")),
             }
         }
-        z += 1;
-        window = (
-            window.1,
-            window.2,
-            match tokens.get_mut(z) {
-                Some(token) => Some(token.tok_type),
-                None => None,
-            },
-        )
+        TokenType::OpenBracket => parse_array(token, tokens),
+        TokenType::OpenBrace => parse_object(token, tokens),
+        TokenType::Function => parse_function_expression(token, tokens),
+        _ => Err(LetscriptError::parse(
+            token.span,
+            format!("expected an expression, found {}", describe(token)),
+        )),
     }
-    let final_tokens = new_tokens
-        .into_iter()
-        .filter(|t| t.is_some())
-        .flatten()
-        .collect();
-
-    return final_tokens;
-}
-
-pub fn precedence(tok: &Token) -> u8 {
-    return match tok.tok_type {
-        //multiplication
-        TokenType::Mult => 255,
-        TokenType::Div => 255,
-        TokenType::Mod => 255,
-        //addition
-        TokenType::Plus => 253,
-        TokenType::Minus => 253,
-        //equality
-        TokenType::In => 252,
-        TokenType::Equals => 252,
-        //logical
-        TokenType::And => 251,
-        TokenType::Or => 251,
-
-        TokenType::GreaterThan => 250,
-        TokenType::LessThan => 250,
-        TokenType::Gte => 250,
-        TokenType::Lte => 250,
-        TokenType::Equals => 250,
-        _ => 0,
-    };
 }
 
-pub fn expr_type_factory(mut itr: vec::IntoIter<Token>) -> Vec<Token> {
-    let mut token = itr.next();
-    let mut new_tokens = Vec::<Token>::new();
+fn string_literal(token: &Token) -> Result<Expr> {
+    Ok(Expr::Literal {
+        value: Value::Str(literal_text(token)?),
+        span: token.span,
+    })
+}
+
+/// Parses the interpolations and text following the `head` of an
+/// interpolated string. Empty text between interpolations is dropped.
+fn parse_template(head: &Token, tokens: &mut TokenIter) -> Result<Expr> {
+    let mut parts = Vec::new();
+    let mut text = head;
     loop {
-        if token.is_none() {
-            break;
+        if !literal_text(text)?.is_empty() {
+            parts.push(string_literal(text)?);
         }
-        match token.clone().unwrap().tok_type {
-            TokenType::OpenParen => {
-                let sub_expr = expr_type_factory(itr.clone());
-                let mut postfix = infix_to_postfix(sub_expr);
-                //TODO: resolve all variables here;
-                let x = eval_expression(&mut postfix);
-                new_tokens.push(x);
-            }
-            TokenType::CloseParen => {
-                break;
-            }
-            TokenType::OpenBracket => {}
-            TokenType::CloseBracket => {}
-            TokenType::Literal
-            | TokenType::And
-            | TokenType::Or
-            | TokenType::Plus
-            | TokenType::Minus
-            | TokenType::Mult
-            | TokenType::Div
-            | TokenType::Mod
-            | TokenType::Not => new_tokens.push(token.clone().unwrap()),
-            _ => panic!("Invalid token in expression"),
+        if text.tok_type == TokenType::TemplateTail {
+            return Ok(Expr::Template {
+                parts,
+                span: head.span.to(text.span),
+            });
         }
-        token = itr.next();
-    }
-    return new_tokens;
-}
-
-pub fn infix_to_postfix(tokens: Vec<Token>) -> Vec<Token> {
-    let mut operator_stack = LinkedList::<Token>::new();
-    let mut operand_queue = Vec::<Token>::new();
-
-    for token in tokens {
-        let is_operand = token.tok_type == TokenType::Literal;
-        if is_operand {
-            operand_queue.push(token.clone())
-        } else if token.tok_type == TokenType::OpenParen {
-            operator_stack.push_front(token.clone());
-        } else if token.tok_type == TokenType::CloseParen {
-            while operator_stack.is_empty() == false
-                && operator_stack.front().unwrap().tok_type != TokenType::OpenParen
+        parts.push(parse_expression(tokens)?);
+        text = match tokens.next() {
+            Some(t)
+                if matches!(
+                    t.tok_type,
+                    TokenType::TemplateMiddle | TokenType::TemplateTail
+                ) =>
             {
-                operand_queue.push(operator_stack.pop_front().unwrap().clone());
+                t
             }
-            operator_stack.pop_front();
-        } else {
-            let top = operator_stack.front();
-            let prec = precedence(&token);
-            let mut stack_prec;
-            match top {
-                Some(x) => {
-                    stack_prec = precedence(x);
-                    if prec <= stack_prec {
-                        let tok = operator_stack.pop_front().unwrap().clone();
-                        operand_queue.push(tok.clone());
-                        stack_prec = precedence(&tok);
-                    }
-                }
-                None => stack_prec = 0,
+            Some(t) => {
+                return Err(LetscriptError::parse(
+                    t.span,
+                    format!(
+                        "expected '}}' to end the interpolation, found {}",
+                        describe(t)
+                    ),
+                ))
             }
-
-            while operator_stack.is_empty() == false && prec < stack_prec {
-                let tok = operator_stack.pop_front().unwrap().clone();
-                stack_prec = precedence(&tok);
-                operand_queue.push(tok.clone());
+            None => {
+                return Err(LetscriptError::parse(
+                    head.span,
+                    "unterminated interpolation",
+                ))
             }
-            operator_stack.push_front(token.clone());
-        }
-    }
-    while operator_stack.is_empty() == false {
-        let tok = operator_stack.pop_front().unwrap().clone();
-        operand_queue.push(tok);
+        };
     }
-
-    // operand_queue
-    //     .iter()
-    //     .for_each(|t| println!("{:?}", t.tok_type));
-
-    return operand_queue;
 }
 
-pub fn eval_expression(postfix_expr: &mut Vec<Token>) -> Token {
-    if postfix_expr.len() as i32 == 1 {
-        return postfix_expr[0].clone();
-    }
-
-    let mut calc_stack = LinkedList::<Token>::new();
-    let mut iter = postfix_expr.into_iter();
-    let mut token_option = iter.next();
-
-    println!("loop VV");
+/// Parses comma-separated expressions up to the token closing `open`.
+/// Newlines and a trailing comma are allowed.
+fn parse_list(
+    open: &Token,
+    close: TokenType,
+    tokens: &mut TokenIter,
+    mut parse_item: impl FnMut(&mut TokenIter) -> Result<()>,
+) -> Result<Span> {
     loop {
-        let token = token_option.unwrap();
-        if precedence(&token.clone()) == 0 {
-            calc_stack.push_front(token.clone())
-        } else {
-            let right = calc_stack.pop_front().unwrap();
-            let left = calc_stack.pop_front().unwrap();
-            let result_token = operate(left, right, token.clone());
-            println!(
-                "result:{}",
-                result_token.clone().tok_value.unwrap().s_val.unwrap()
-            );
-            calc_stack.push_front(result_token.clone());
+        skip_newlines(tokens);
+        if let Some(end) = tokens.next_if(|t| t.tok_type == close) {
+            return Ok(open.span.to(end.span));
         }
-        token_option = iter.next();
-        if calc_stack.len() == 1 && token_option.is_none() {
-            break;
+        if tokens.peek().is_none() {
+            return Err(LetscriptError::parse(
+                open.span,
+                format!("unclosed {}", describe(open)),
+            ));
+        }
+        parse_item(tokens)?;
+        skip_newlines(tokens);
+        match tokens.peek() {
+            Some(t) if t.tok_type == TokenType::Comma => {
+                tokens.next();
+            }
+            Some(t) if t.tok_type == close => {}
+            Some(t) => {
+                return Err(LetscriptError::parse(
+                    t.span,
+                    format!("expected ',' or the end of the list, found {}", describe(t)),
+                ))
+            }
+            None => {
+                return Err(LetscriptError::parse(
+                    open.span,
+                    format!("unclosed {}", describe(open)),
+                ))
+            }
         }
     }
-    let ret_val = calc_stack.front().unwrap();
-    println!(
-        "returns {}",
-        &ret_val.clone().tok_value.unwrap().s_val.unwrap()
-    );
-    return ret_val.clone();
+}
+
+fn parse_arguments(open: &Token, tokens: &mut TokenIter) -> Result<(Vec<Expr>, Span)> {
+    let mut args = Vec::new();
+    let span = parse_list(open, TokenType::CloseParen, tokens, |tokens| {
+        args.push(parse_expression(tokens)?);
+        Ok(())
+    })?;
+    Ok((args, span))
+}
+
+fn parse_array(open: &Token, tokens: &mut TokenIter) -> Result<Expr> {
+    let mut elements = Vec::new();
+    let span = parse_list(open, TokenType::CloseBracket, tokens, |tokens| {
+        elements.push(parse_expression(tokens)?);
+        Ok(())
+    })?;
+    Ok(Expr::Array { elements, span })
+}
+
+fn parse_object(open: &Token, tokens: &mut TokenIter) -> Result<Expr> {
+    let mut entries = Vec::new();
+    let span = parse_list(open, TokenType::CloseBrace, tokens, |tokens| {
+        let key_tok = tokens.next().unwrap();
+        let key = match key_tok.tok_type {
+            TokenType::Str => string_literal(key_tok)?,
+            _ => Expr::Literal {
+                value: Value::Str(parse_name(key_tok)?),
+                span: key_tok.span,
+            },
+        };
+        expect(tokens, TokenType::Colon, "':' after the field name")?;
+        entries.push((key, parse_expression(tokens)?));
+        Ok(())
+    })?;
+    Ok(Expr::Object { entries, span })
 }
@@ -1,4 +1,215 @@
-use crate::{
-    ast::{Scope, SymbolType},
-    tokenizer::tokenize,
-};
+use crate::ast::{Expr, Stmt};
+use crate::parser::parse;
+use crate::token::TokenType;
+use crate::tokenizer::tokenize;
+use crate::value::Value;
+
+/// Parses a single expression statement.
+fn expression(source: &str) -> Expr {
+    let program = parse(&tokenize(source).unwrap()).unwrap();
+    match program.body.last() {
+        Some(Stmt::ExprStmt(expr)) => expr.clone(),
+        other => panic!("expected an expression statement, got {:?}", other),
+    }
+}
+
+/// Renders an expression as an s-expression so tests can check its shape.
+fn sexpr(expr: &Expr) -> String {
+    let op = |op: &TokenType| match op {
+        TokenType::Plus => "+",
+        TokenType::Minus => "-",
+        TokenType::Mult => "*",
+        TokenType::Div => "/",
+        TokenType::Mod => "mod",
+        TokenType::Pow => "**",
+        TokenType::Equals => "=",
+        TokenType::NotEqual => "not=",
+        TokenType::Not => "not",
+        TokenType::And => "and",
+        TokenType::Or => "or",
+        TokenType::GreaterThan => ">",
+        TokenType::LessThan => "<",
+        TokenType::Gte => ">=",
+        TokenType::Lte => "<=",
+        TokenType::In => "in",
+        TokenType::Is => "is",
+        _ => "?",
+    };
+    let list = |exprs: &[Expr]| exprs.iter().map(sexpr).collect::<Vec<_>>().join(" ");
+    match expr {
+        Expr::Literal {
+            value: Value::Str(s),
+            ..
+        } => format!("{:?}", s),
+        Expr::Literal { value, .. } => value.to_string(),
+        Expr::Variable { name, .. } => name.clone(),
+        Expr::Unary { op: o, operand, .. } => format!("({} {})", op(o), sexpr(operand)),
+        Expr::Binary {
+            op: o, left, right, ..
+        } => format!("({} {} {})", op(o), sexpr(left), sexpr(right)),
+        Expr::Call { callee, args, .. } => format!("(call {} [{}])", sexpr(callee), list(args)),
+        Expr::Index { target, index, .. } => format!("(index {} {})", sexpr(target), sexpr(index)),
+        Expr::Slice {
+            target, start, end, ..
+        } => {
+            let bound =
+                |bound: &Option<Box<Expr>>| bound.as_deref().map_or(String::from("_"), sexpr);
+            format!("(slice {} {} {})", sexpr(target), bound(start), bound(end))
+        }
+        Expr::Member { target, name, .. } => format!("(. {} {})", sexpr(target), name),
+        Expr::Array { elements, .. } => format!("[{}]", list(elements)),
+        Expr::Template { parts, .. } => format!("(template {})", list(parts)),
+        Expr::Function { params, body, .. } => {
+            format!("(function [{}] {})", params.join(" "), body.body.len())
+        }
+        Expr::Object { entries, .. } => format!(
+            "{{{}}}",
+            entries
+                .iter()
+                .map(|(k, v)| format!("{}: {}", sexpr(k), sexpr(v)))
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+    }
+}
+
+#[test]
+pub fn test_binary_precedence() {
+    assert_eq!(sexpr(&expression("5+ 7 or 3 * 5")), "(or (+ 5 7) (* 3 5))");
+    assert_eq!(sexpr(&expression("(3 - 5) * 12")), "(* (- 3 5) 12)");
+    assert_eq!(sexpr(&expression("(3 * 13) mod 12")), "(mod (* 3 13) 12)");
+    assert_eq!(sexpr(&expression("1 - 2 - 3")), "(- (- 1 2) 3)");
+    assert_eq!(
+        sexpr(&expression("((1 +4)  = 5) and 2 = 2")),
+        "(and (= (+ 1 4) 5) (= 2 2))"
+    );
+}
+
+#[test]
+pub fn test_operator_precedence_levels() {
+    assert_eq!(
+        sexpr(&expression("a < b and c >= d")),
+        "(and (< a b) (>= c d))"
+    );
+    assert_eq!(
+        sexpr(&expression("a or b and c or d")),
+        "(or (or a (and b c)) d)"
+    );
+    assert_eq!(
+        sexpr(&expression("x in items = flag")),
+        "(= (in x items) flag)"
+    );
+    assert_eq!(
+        sexpr(&expression("a + b * c ** 2 > 10")),
+        "(> (+ a (* b (** c 2))) 10)"
+    );
+    assert_eq!(
+        sexpr(&expression("a is b and c not= d")),
+        "(and (is a b) (not= c d))"
+    );
+    assert_eq!(sexpr(&expression("2 ** 3 ** 2")), "(** 2 (** 3 2))");
+    assert_eq!(sexpr(&expression("8 / 4 / 2")), "(/ (/ 8 4) 2)");
+    assert_eq!(sexpr(&expression("-x ** 2")), "(- (** x 2))");
+    assert_eq!(sexpr(&expression("2 ** -1")), "(** 2 -1)");
+    assert_eq!(sexpr(&expression("not a = b or c")), "(or (not (= a b)) c)");
+    assert_eq!(sexpr(&expression("not 3 in items")), "(not (in 3 items))");
+    assert_eq!(sexpr(&expression("not a and b")), "(and (not a) b)");
+    assert_eq!(sexpr(&expression("not -x < 2")), "(not (< (- x) 2))");
+}
+
+#[test]
+pub fn test_unary_operators() {
+    assert_eq!(sexpr(&expression("3 + -5")), "(+ 3 -5)");
+    assert_eq!(sexpr(&expression("-x * 2")), "(* (- x) 2)");
+    assert_eq!(sexpr(&expression("not (a and b)")), "(not (and a b))");
+    assert_eq!(sexpr(&expression("a not= b")), "(not= a b)");
+    assert_eq!(sexpr(&expression("a == b + 1")), "(= a (+ b 1))");
+    assert_eq!(sexpr(&expression("a not = b + 1")), "(not= a (+ b 1))");
+    assert_eq!(sexpr(&expression("- - 5")), "5");
+    assert_eq!(sexpr(&expression("not not a")), "(not (not a))");
+}
+
+#[test]
+pub fn test_postfix_operations() {
+    assert_eq!(
+        sexpr(&expression("add(1, x * 2)")),
+        "(call add [1 (* x 2)])"
+    );
+    assert_eq!(
+        sexpr(&expression("rows[1][0] + 1")),
+        "(+ (index (index rows 1) 0) 1)"
+    );
+    assert_eq!(sexpr(&expression("-items[0]")), "(- (index items 0))");
+    assert_eq!(sexpr(&expression("(f)(x)()")), "(call (call f [x]) [])");
+    assert_eq!(
+        sexpr(&expression("user.name.first + config[\"key\"].size")),
+        "(+ (. (. user name) first) (. (index config \"key\") size))"
+    );
+    assert_eq!(sexpr(&expression("-point.x")), "(- (. point x))");
+    assert_eq!(sexpr(&expression("items[1:3]")), "(slice items 1 3)");
+    assert_eq!(
+        sexpr(&expression("items[:-1][i + 1:]")),
+        "(slice (slice items _ -1) (+ i 1) _)"
+    );
+    assert_eq!(sexpr(&expression("items[:]")), "(slice items _ _)");
+}
+
+#[test]
+pub fn test_collection_literals() {
+    assert_eq!(sexpr(&expression("[1, [2, 3], \"a\"]")), "[1 [2 3] \"a\"]");
+    assert_eq!(sexpr(&expression("[\n  1,\n  2,\n]")), "[1 2]");
+    assert_eq!(
+        sexpr(&expression("({ name: \"x\", nested: { a: 1 } })")),
+        "{\"name\": \"x\", \"nested\": {\"a\": 1}}"
+    );
+}
+
+#[test]
+pub fn test_expression_spans() {
+    let expr = expression("  10 + size * 2");
+    let span = expr.span();
+    assert_eq!(
+        (span.line, span.column, span.start, span.end),
+        (1, 3, 2, 15)
+    );
+    match &expr {
+        Expr::Binary { right, .. } => assert_eq!(right.span().start, 7),
+        other => panic!("expected a binary expression, got {:?}", other),
+    }
+}
+
+#[test]
+pub fn test_function_literals() {
+    assert_eq!(
+        sexpr(&expression(
+            "apply(function (a, b) {\n  var c is a\n  return c + b\n}, 1)"
+        )),
+        "(call apply [(function [a b] 2) 1])"
+    );
+    assert_eq!(
+        sexpr(&expression("function () { }()")),
+        "(call (function [] 0) [])"
+    );
+}
+
+#[test]
+pub fn test_string_interpolation() {
+    assert_eq!(
+        sexpr(&expression(
+            "\"Hello ${name}, you have ${count + 1} items\""
+        )),
+        "(template \"Hello \" name \", you have \" (+ count 1) \" items\")"
+    );
+    assert_eq!(
+        sexpr(&expression("\"${a}${ \"${b}!\" }\" + 1")),
+        "(+ (template a (template b \"!\")) 1)"
+    );
+    let template = expression("print('x${ f(1, 2) }')");
+    match &template {
+        Expr::Call { args, .. } => {
+            assert_eq!(sexpr(&args[0]), "(template \"x\" (call f [1 2]))");
+            assert_eq!((args[0].span().start, args[0].span().end), (6, 21));
+        }
+        other => panic!("expected a call, got {:?}", other),
+    }
+}
@@ -1,97 +1,572 @@
-use crate::expr::operate;
-use crate::parser::collect_expression_tokens;
-use crate::parser::infix_to_postfix;
-use crate::parser::precedence;
-use crate::token::TokenType;
+use crate::error::LetscriptError;
+use crate::expr::{operate, operate_unary, Overflow};
+use crate::parser::literal_value;
+use crate::token::{Span, Token, TokenType};
 use crate::tokenizer::tokenize;
+use crate::value::Value;
+use num_bigint::BigInt;
+use std::collections::BTreeMap;
 
 #[test]
 pub fn test_operation_1() {
-    let mut input = String::from("3+4 ");
-    let tokens = tokenize(&mut input);
+    let input = String::from("3+4 ");
+    let tokens = tokenize(&input).unwrap();
     tokens.iter().for_each(|t| match t.tok_type {
         TokenType::Literal => println!("TOKEN:{:?}", t.clone().tok_value.unwrap().s_val),
         _ => println!("TOKEN:{:?}", t.tok_type),
     });
-    let result = operate(tokens[0].clone(), tokens[2].clone(), tokens[1].clone());
+    let left = literal_value(&tokens[0]).unwrap();
+    let right = literal_value(&tokens[2]).unwrap();
+    let result = operate(&left, &right, &tokens[1], Overflow::Promote).unwrap();
 
-    assert_eq!(result.tok_value.unwrap().s_val.unwrap(), "7")
+    assert_eq!(result.to_string(), "7")
 }
 
 #[test]
 pub fn test_operation_2() {
-    let mut input = String::from("37-17 ");
-    let tokens = tokenize(&mut input);
+    let input = String::from("37-17 ");
+    let tokens = tokenize(&input).unwrap();
     tokens.iter().for_each(|t| match t.tok_type {
         TokenType::Literal => println!("TOKEN:{:?}", t.clone().tok_value.unwrap().s_val),
         _ => println!("TOKEN:{:?}", t.tok_type),
     });
-    let result = operate(tokens[0].clone(), tokens[2].clone(), tokens[1].clone());
+    let left = literal_value(&tokens[0]).unwrap();
+    let right = literal_value(&tokens[2]).unwrap();
+    let result = operate(&left, &right, &tokens[1], Overflow::Promote).unwrap();
 
-    assert_eq!(result.tok_value.unwrap().s_val.unwrap(), "20")
+    assert_eq!(result.to_string(), "20")
 }
 
 #[test]
 pub fn test_operation_3() {
-    let mut input = String::from("5.1 * 5.0 ");
-    let tokens = tokenize(&mut input);
+    let input = String::from("5.1 * 5.0 ");
+    let tokens = tokenize(&input).unwrap();
     tokens.iter().for_each(|t| match t.tok_type {
         TokenType::Literal => println!("TOKEN:{:?}", t.clone().tok_value.unwrap().s_val),
         _ => println!("TOKEN:{:?}", t.tok_type),
     });
-    let result = operate(tokens[0].clone(), tokens[2].clone(), tokens[1].clone());
+    let left = literal_value(&tokens[0]).unwrap();
+    let right = literal_value(&tokens[2]).unwrap();
+    let result = operate(&left, &right, &tokens[1], Overflow::Promote).unwrap();
 
-    assert_eq!(result.tok_value.unwrap().s_val.unwrap(), "25.5")
+    assert_eq!(result.to_string(), "25.5")
 }
 
 #[test]
 pub fn test_operation_4() {
-    let mut input = String::from("8/2 ");
-    let tokens = tokenize(&mut input);
+    let input = String::from("8/2 ");
+    let tokens = tokenize(&input).unwrap();
     tokens.iter().for_each(|t| match t.tok_type {
         TokenType::Literal => println!("TOKEN:{:?}", t.clone().tok_value.unwrap().s_val),
         _ => println!("TOKEN:{:?}", t.tok_type),
     });
-    let result = operate(tokens[0].clone(), tokens[2].clone(), tokens[1].clone());
+    let left = literal_value(&tokens[0]).unwrap();
+    let right = literal_value(&tokens[2]).unwrap();
+    let result = operate(&left, &right, &tokens[1], Overflow::Promote).unwrap();
 
-    assert_eq!(result.tok_value.unwrap().s_val.unwrap(), "4")
+    assert_eq!(result.to_string(), "4")
 }
 
 #[test]
 pub fn test_operation_5() {
-    let mut input = String::from("8.6/2 ");
-    let tokens = tokenize(&mut input);
+    let input = String::from("8.6/2 ");
+    let tokens = tokenize(&input).unwrap();
     tokens.iter().for_each(|t| match t.tok_type {
         TokenType::Literal => println!("TOKEN:{:?}", t.clone().tok_value.unwrap().s_val),
         _ => println!("TOKEN:{:?}", t.tok_type),
     });
-    let result = operate(tokens[0].clone(), tokens[2].clone(), tokens[1].clone());
+    let left = literal_value(&tokens[0]).unwrap();
+    let right = literal_value(&tokens[2]).unwrap();
+    let result = operate(&left, &right, &tokens[1], Overflow::Promote).unwrap();
 
-    assert_eq!(result.tok_value.unwrap().s_val.unwrap(), "4.3")
+    assert_eq!(result.to_string(), "4.3")
 }
 
 #[test]
 pub fn test_operation_6() {
-    let mut input = String::from("1 and false ");
-    let tokens = tokenize(&mut input);
+    let input = String::from("1 and false ");
+    let tokens = tokenize(&input).unwrap();
     tokens.iter().for_each(|t| match t.tok_type {
         TokenType::Literal => println!("TOKEN:{:?}", t.clone().tok_value.unwrap().s_val),
         _ => println!("TOKEN:{:?}", t.tok_type),
     });
-    let result = operate(tokens[0].clone(), tokens[2].clone(), tokens[1].clone());
+    let left = literal_value(&tokens[0]).unwrap();
+    let right = literal_value(&tokens[2]).unwrap();
+    let result = operate(&left, &right, &tokens[1], Overflow::Promote).unwrap();
 
-    assert_eq!(result.tok_value.unwrap().s_val.unwrap(), "false")
+    assert_eq!(result.to_string(), "false")
 }
 
 #[test]
 pub fn test_operation_7() {
-    let mut input = String::from("1.1 or false ");
-    let tokens = tokenize(&mut input);
+    let input = String::from("1.1 or false ");
+    let tokens = tokenize(&input).unwrap();
     tokens.iter().for_each(|t| match t.tok_type {
         TokenType::Literal => println!("TOKEN:{:?}", t.clone().tok_value.unwrap().s_val),
         _ => println!("TOKEN:{:?}", t.tok_type),
     });
-    let result = operate(tokens[0].clone(), tokens[2].clone(), tokens[1].clone());
+    let left = literal_value(&tokens[0]).unwrap();
+    let right = literal_value(&tokens[2]).unwrap();
+    let result = operate(&left, &right, &tokens[1], Overflow::Promote).unwrap();
 
-    assert_eq!(result.tok_value.unwrap().s_val.unwrap(), "true")
+    assert_eq!(result.to_string(), "true")
+}
+
+#[test]
+pub fn test_operation_type_error() {
+    let input = String::from("1 and nothing ");
+    let tokens = tokenize(&input).unwrap();
+    let result = operate(
+        &Value::Int(1),
+        &Value::Undefined,
+        &tokens[1],
+        Overflow::Promote,
+    );
+
+    assert!(matches!(result, Err(LetscriptError::Type { span, .. }) if span == tokens[1].span));
+}
+
+#[test]
+pub fn test_unknown_operator() {
+    let tokens = tokenize("1, 2").unwrap();
+    let result = operate(
+        &Value::Int(1),
+        &Value::Int(2),
+        &tokens[1],
+        Overflow::Promote,
+    );
+    assert!(matches!(result, Err(LetscriptError::Runtime { .. })));
+    let result = operate_unary(&Value::Int(1), &tokens[1], Overflow::Promote);
+    assert!(matches!(result, Err(LetscriptError::Runtime { .. })));
+}
+
+#[test]
+pub fn test_operation_keeps_float_precision() {
+    let third = operate(
+        &Value::Float(1.0),
+        &Value::Int(3),
+        &tokenize("/").unwrap()[0],
+        Overflow::Promote,
+    )
+    .unwrap();
+    assert_eq!(third, Value::Float(1.0 / 3.0));
+    assert!(matches!(
+        operate(
+            &Value::Str(String::from("a")),
+            &Value::Int(1),
+            &tokenize("+").unwrap()[0],
+            Overflow::Promote,
+        ),
+        Err(LetscriptError::Type { .. })
+    ));
+}
+
+fn operator(tok_type: TokenType) -> Token<'static> {
+    Token {
+        tok_type,
+        tok_value: None,
+        span: Span::default(),
+        trivia: Vec::new(),
+    }
+}
+
+/// One value of every type, with ints both small and big.
+fn samples() -> Vec<Value> {
+    vec![
+        Value::Int(7),
+        Value::BigInt(BigInt::from(1u128 << 70)),
+        Value::Float(2.5),
+        Value::Bool(true),
+        Value::Null,
+        Value::Undefined,
+        Value::Str(String::from("a")),
+        Value::array(vec![Value::Int(1)]),
+        Value::object(BTreeMap::new()),
+    ]
+}
+
+#[test]
+pub fn test_operator_matrix() {
+    let number =
+        |value: &Value| matches!(value, Value::Int(_) | Value::BigInt(_) | Value::Float(_));
+    let int = |value: &Value| matches!(value, Value::Int(_) | Value::BigInt(_));
+    let logical = |value: &Value| number(value) || matches!(value, Value::Bool(_) | Value::Null);
+    let string = |value: &Value| matches!(value, Value::Str(_));
+
+    let samples = samples();
+    for (i, left) in samples.iter().enumerate() {
+        for (j, right) in samples.iter().enumerate() {
+            let run = |tok_type| operate(left, right, &operator(tok_type), Overflow::Promote);
+            let case = format!("{:?} and {:?}", left, right);
+
+            for tok_type in [
+                TokenType::Plus,
+                TokenType::Minus,
+                TokenType::Mult,
+                TokenType::Div,
+                TokenType::Mod,
+            ] {
+                match run(tok_type) {
+                    Ok(Value::Str(_)) if tok_type == TokenType::Plus => {
+                        assert!(string(left) && string(right), "{}", case)
+                    }
+                    Ok(Value::Str(_)) if tok_type == TokenType::Mult => assert!(
+                        (string(left) && int(right)) || (int(left) && string(right)),
+                        "{}",
+                        case
+                    ),
+                    // Repeating a string by a big integer.
+                    Err(LetscriptError::Runtime { .. }) if tok_type == TokenType::Mult => assert!(
+                        matches!(
+                            (left, right),
+                            (Value::Str(_), Value::BigInt(_)) | (Value::BigInt(_), Value::Str(_))
+                        ),
+                        "{}",
+                        case
+                    ),
+                    Ok(Value::Int(_) | Value::BigInt(_)) => {
+                        assert!(int(left) && int(right), "{:?}: {}", tok_type, case)
+                    }
+                    Ok(Value::Float(_)) => assert!(
+                        number(left) && number(right) && !(int(left) && int(right)),
+                        "{:?}: {}",
+                        tok_type,
+                        case
+                    ),
+                    Err(LetscriptError::Type { .. }) => {
+                        assert!(!(number(left) && number(right)), "{:?}: {}", tok_type, case)
+                    }
+                    other => panic!("{:?}: {} gave {:?}", tok_type, case, other),
+                }
+            }
+            for tok_type in [
+                TokenType::GreaterThan,
+                TokenType::LessThan,
+                TokenType::Gte,
+                TokenType::Lte,
+            ] {
+                match run(tok_type) {
+                    Ok(Value::Bool(_)) => {
+                        assert!((number(left) && number(right)) || (string(left) && string(right)))
+                    }
+                    Err(LetscriptError::Type { .. }) => assert!(
+                        !((number(left) && number(right)) || (string(left) && string(right)))
+                    ),
+                    other => panic!("{:?}: {} gave {:?}", tok_type, case, other),
+                }
+            }
+            match run(TokenType::Pow) {
+                Ok(Value::Int(_) | Value::BigInt(_) | Value::Float(_)) => {
+                    assert!(number(left) && number(right), "{}", case)
+                }
+                // Only a huge int exponent is rejected.
+                Err(LetscriptError::Runtime { .. }) => {
+                    assert!(matches!(right, Value::BigInt(_)), "{}", case)
+                }
+                Err(LetscriptError::Type { .. }) => {
+                    assert!(!(number(left) && number(right)), "{}", case)
+                }
+                other => panic!("Pow: {} gave {:?}", case, other),
+            }
+            assert_eq!(run(TokenType::Equals), Ok(Value::Bool(i == j)), "{}", case);
+            assert_eq!(run(TokenType::Is), Ok(Value::Bool(i == j)), "{}", case);
+            assert_eq!(
+                run(TokenType::NotEqual),
+                Ok(Value::Bool(i != j)),
+                "{}",
+                case
+            );
+            for tok_type in [TokenType::And, TokenType::Or] {
+                match run(tok_type) {
+                    Ok(Value::Bool(_)) => assert!(logical(left) && logical(right)),
+                    Err(LetscriptError::Type { .. }) => assert!(!(logical(left) && logical(right))),
+                    other => panic!("{:?}: {} gave {:?}", tok_type, case, other),
+                }
+            }
+        }
+    }
+}
+
+#[test]
+pub fn test_operator_results() {
+    let big = || Value::BigInt(BigInt::from(1u128 << 70));
+    let cases = [
+        (
+            Value::Int(2),
+            TokenType::Pow,
+            Value::Int(-2),
+            Value::Float(0.25),
+        ),
+        (
+            Value::Float(4.0),
+            TokenType::Pow,
+            Value::Float(0.5),
+            Value::Float(2.0),
+        ),
+        (Value::Int(-1), TokenType::Pow, big(), Value::Int(1)),
+        (
+            big(),
+            TokenType::Pow,
+            Value::Int(2),
+            Value::BigInt(BigInt::from(1u128 << 70).pow(2u32)),
+        ),
+        (
+            Value::Int(7),
+            TokenType::Minus,
+            Value::Float(2.5),
+            Value::Float(4.5),
+        ),
+        (
+            Value::Float(2.5),
+            TokenType::Minus,
+            Value::Int(7),
+            Value::Float(-4.5),
+        ),
+        (
+            Value::Float(7.5),
+            TokenType::Minus,
+            Value::Float(2.5),
+            Value::Float(5.0),
+        ),
+        (
+            Value::Float(1.5),
+            TokenType::Plus,
+            Value::Float(2.0),
+            Value::Float(3.5),
+        ),
+        (
+            Value::Float(1.5),
+            TokenType::Mult,
+            Value::Int(2),
+            Value::Float(3.0),
+        ),
+        (
+            Value::Float(1.0),
+            TokenType::Div,
+            Value::Float(4.0),
+            Value::Float(0.25),
+        ),
+        (
+            Value::Int(1),
+            TokenType::Div,
+            Value::Float(4.0),
+            Value::Float(0.25),
+        ),
+        (
+            Value::Float(10.0),
+            TokenType::Div,
+            Value::Int(4),
+            Value::Float(2.5),
+        ),
+        (Value::Int(7), TokenType::Div, Value::Int(2), Value::Int(3)),
+        (
+            Value::Int(-7),
+            TokenType::Div,
+            Value::Int(2),
+            Value::Int(-3),
+        ),
+        (
+            Value::Int(-7),
+            TokenType::Mod,
+            Value::Int(2),
+            Value::Int(-1),
+        ),
+        (
+            Value::Float(7.5),
+            TokenType::Mod,
+            Value::Int(2),
+            Value::Float(1.5),
+        ),
+        (
+            Value::Int(2),
+            TokenType::Mod,
+            Value::Float(7.5),
+            Value::Float(2.0),
+        ),
+        (
+            Value::Float(7.5),
+            TokenType::Mod,
+            Value::Float(2.0),
+            Value::Float(1.5),
+        ),
+        (
+            big(),
+            TokenType::Minus,
+            Value::Int(1),
+            Value::BigInt(BigInt::from((1u128 << 70) - 1)),
+        ),
+        (
+            Value::Int(1),
+            TokenType::Minus,
+            big(),
+            Value::BigInt(-BigInt::from((1u128 << 70) - 1)),
+        ),
+        (big(), TokenType::Div, big(), Value::Int(1)),
+        (
+            big(),
+            TokenType::Mult,
+            Value::Float(0.5),
+            Value::Float((1u128 << 69) as f64),
+        ),
+        (
+            Value::Int(3),
+            TokenType::LessThan,
+            Value::Float(3.5),
+            Value::Bool(true),
+        ),
+        (
+            Value::Float(3.5),
+            TokenType::LessThan,
+            Value::Int(3),
+            Value::Bool(false),
+        ),
+        (
+            Value::Int(3),
+            TokenType::Gte,
+            Value::Float(3.0),
+            Value::Bool(true),
+        ),
+        (
+            Value::Int(-1),
+            TokenType::LessThan,
+            big(),
+            Value::Bool(true),
+        ),
+        (
+            Value::Float(f64::NAN),
+            TokenType::Lte,
+            Value::Float(f64::NAN),
+            Value::Bool(false),
+        ),
+        (
+            Value::Int(1),
+            TokenType::Equals,
+            Value::Float(1.0),
+            Value::Bool(true),
+        ),
+        (
+            Value::Float(2.0),
+            TokenType::NotEqual,
+            Value::Int(2),
+            Value::Bool(false),
+        ),
+        (
+            Value::Bool(false),
+            TokenType::Equals,
+            Value::Int(0),
+            Value::Bool(false),
+        ),
+        (
+            Value::Null,
+            TokenType::Equals,
+            Value::Undefined,
+            Value::Bool(false),
+        ),
+        (
+            Value::Str(String::from("a")),
+            TokenType::Equals,
+            Value::Str(String::from("b")),
+            Value::Bool(false),
+        ),
+        (
+            Value::Bool(false),
+            TokenType::Or,
+            Value::Float(0.5),
+            Value::Bool(true),
+        ),
+        (
+            Value::Null,
+            TokenType::Or,
+            Value::Int(0),
+            Value::Bool(false),
+        ),
+        (
+            Value::Bool(true),
+            TokenType::And,
+            Value::Bool(false),
+            Value::Bool(false),
+        ),
+    ];
+    for (left, tok_type, right, expected) in cases {
+        assert_eq!(
+            operate(&left, &right, &operator(tok_type), Overflow::Promote),
+            Ok(expected),
+            "{:?} {:?} {:?}",
+            left,
+            tok_type,
+            right
+        );
+    }
+}
+
+#[test]
+pub fn test_string_operators() {
+    let text = |s: &str| Value::Str(String::from(s));
+    let run = |left: Value, tok_type, right: Value| {
+        operate(&left, &right, &operator(tok_type), Overflow::Promote)
+    };
+    assert_eq!(
+        run(text("foo"), TokenType::Plus, text("bar")),
+        Ok(text("foobar"))
+    );
+    assert_eq!(
+        run(text("ab"), TokenType::Mult, Value::Int(3)),
+        Ok(text("ababab"))
+    );
+    assert_eq!(
+        run(Value::Int(2), TokenType::Mult, text("-")),
+        Ok(text("--"))
+    );
+    assert_eq!(
+        run(text("ab"), TokenType::Mult, Value::Int(0)),
+        Ok(text(""))
+    );
+    assert_eq!(
+        run(text("apple"), TokenType::LessThan, text("banana")),
+        Ok(Value::Bool(true))
+    );
+    assert_eq!(
+        run(text("Zebra"), TokenType::LessThan, text("apple")),
+        Ok(Value::Bool(true))
+    );
+    assert_eq!(
+        run(text("abc"), TokenType::Gte, text("ab")),
+        Ok(Value::Bool(true))
+    );
+    assert_eq!(
+        run(text("é"), TokenType::GreaterThan, text("z")),
+        Ok(Value::Bool(true))
+    );
+    assert_eq!(
+        run(text("a"), TokenType::NotEqual, text("a")),
+        Ok(Value::Bool(false))
+    );
+    assert_eq!(
+        run(text("1"), TokenType::Equals, Value::Int(1)),
+        Ok(Value::Bool(false))
+    );
+
+    for (left, tok_type, right) in [
+        (text("a"), TokenType::Plus, Value::Int(1)),
+        (Value::Float(1.5), TokenType::Plus, text("a")),
+        (text("a"), TokenType::Minus, text("a")),
+        (text("a"), TokenType::Mult, text("a")),
+        (text("a"), TokenType::Mult, Value::Float(2.0)),
+        (text("a"), TokenType::LessThan, Value::Int(1)),
+    ] {
+        assert!(matches!(
+            run(left, tok_type, right),
+            Err(LetscriptError::Type { .. })
+        ));
+    }
+    assert!(matches!(
+        run(text("a"), TokenType::Mult, Value::Int(-1)),
+        Err(LetscriptError::Runtime { message, .. }) if message.contains("negative")
+    ));
+    for count in [Value::Int(99_999_999_999_999), Value::Int(i64::MAX)] {
+        assert!(matches!(
+            run(text("a"), TokenType::Mult, count),
+            Err(LetscriptError::Runtime { message, .. }) if message.contains("too long")
+        ));
+    }
 }
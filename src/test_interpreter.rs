@@ -0,0 +1,718 @@
+use crate::error::LetscriptError;
+use crate::interpreter::Interpreter;
+use crate::parser::parse;
+use crate::tokenizer::tokenize;
+use crate::value::Value;
+use std::rc::Rc;
+
+fn run(source: &str) -> Interpreter {
+    let mut interpreter = Interpreter::new();
+    interpreter
+        .run(&parse(&tokenize(source).unwrap()).unwrap())
+        .unwrap();
+    interpreter
+}
+
+fn run_err(source: &str) -> LetscriptError {
+    let mut interpreter = Interpreter::new();
+    interpreter
+        .run(&parse(&tokenize(source).unwrap()).unwrap())
+        .unwrap_err()
+}
+
+fn evaluate(expression: &str) -> Value {
+    let interpreter = run(&format!("var result is {}", expression));
+    interpreter.lookup("result").unwrap()
+}
+
+#[test]
+pub fn test_evaluate_expressions() {
+    assert_eq!(evaluate("5+ 7 or 3 * 5").to_string(), "true");
+    assert_eq!(evaluate("(3 - 5) * 12").to_string(), "-24");
+    assert_eq!(evaluate("(3 * 13) mod 12").to_string(), "3");
+    assert_eq!(evaluate("(42.6 /6) + 1").to_string(), "8.100000000000001");
+    assert_eq!(evaluate("5 > 7").to_string(), "false");
+    assert_eq!(evaluate("5 >= 5").to_string(), "true");
+    assert_eq!(evaluate("5 = 5 and 3 = 2").to_string(), "false");
+    assert_eq!(evaluate("((1 +4)  = 5) and 2 = 2").to_string(), "true");
+    assert_eq!(evaluate("-(2 * 3)").to_string(), "-6");
+    assert_eq!(evaluate("1 < 2 and 3 > 4").to_string(), "false");
+    assert_eq!(evaluate("true or false and false").to_string(), "true");
+    assert_eq!(evaluate("2 ** 3 ** 2").to_string(), "512");
+    assert_eq!(evaluate("-2 ** 2").to_string(), "-4");
+    assert_eq!(evaluate("2 ** -1").to_string(), "0.5");
+    assert_eq!(evaluate("1.5 ** 2").to_string(), "2.25");
+    assert_eq!(evaluate("2 ** 64").to_string(), "18446744073709551616");
+}
+
+#[test]
+pub fn test_variables() {
+    let interpreter =
+        run("var first is 3 + 4\n\tvar second is (first * 2) mod 5\r\nfirst = first + 1\n");
+    assert_eq!(interpreter.lookup("first"), Some(Value::Int(8)));
+    assert_eq!(interpreter.lookup("second"), Some(Value::Int(4)));
+}
+
+#[test]
+pub fn test_block_scoping() {
+    let interpreter = run(
+        "var outer is 1\n{\n  var inner is 2\n  var outer is 10\n  outer = outer + inner\n}\n\
+         {\n  outer = outer + 1\n}\n",
+    );
+    assert_eq!(interpreter.lookup("outer"), Some(Value::Int(2)));
+    assert_eq!(interpreter.lookup("inner"), None);
+
+    let err = run_err("{\n  var hidden is 1\n}\nvar shown is hidden\n");
+    assert_eq!(err, LetscriptError::name(err.span(), "hidden"));
+}
+
+#[test]
+pub fn test_control_flow() {
+    let interpreter = run(
+        "var total is 0\nvar n is 0\nwhile n < 5 {\n  n = n + 1\n  total = total + n\n}\n\
+         for (var i is 0, i < 3, i = i + 1) {\n  total = total + 100\n}\n\
+         each x in [1, 2, 3] {\n  total = total + x\n}\n\
+         var sign is 0\nif total > 300 {\n  sign = 1\n} else {\n  sign = -1\n}\n",
+    );
+    assert_eq!(interpreter.lookup("total"), Some(Value::Int(321)));
+    assert_eq!(interpreter.lookup("sign"), Some(Value::Int(1)));
+    assert_eq!(interpreter.lookup("i"), None);
+    assert_eq!(interpreter.lookup("x"), None);
+}
+
+#[test]
+pub fn test_runtime_errors() {
+    let err = run_err("var known is 1\nvar other is known + unknown\n");
+    assert_eq!(err, LetscriptError::name(err.span(), "unknown"));
+    assert_eq!((err.span().line, err.span().column), (2, 22));
+
+    let err = run_err("missing = 3\n");
+    assert_eq!(err, LetscriptError::name(err.span(), "missing"));
+
+    assert!(matches!(run_err("if [] { }"), LetscriptError::Type { .. }));
+    assert!(matches!(
+        run_err("var n is 3\nn()"),
+        LetscriptError::Type { .. }
+    ));
+    assert!(matches!(
+        run_err("print(type(1, 2))"),
+        LetscriptError::Runtime { .. }
+    ));
+    assert!(matches!(
+        run_err("var total is [1] + 2"),
+        LetscriptError::Type { .. }
+    ));
+}
+
+#[test]
+pub fn test_typed_values() {
+    assert_eq!(evaluate("7 / 2"), Value::Int(3));
+    assert_eq!(evaluate("0.1 + 0.2"), Value::Float(0.1 + 0.2));
+    assert_eq!(evaluate("not false"), Value::Bool(true));
+    assert_eq!(evaluate("null"), Value::Null);
+    assert_eq!(evaluate("0xFF + 0b1 * 1_000"), Value::Int(1255));
+    assert_eq!(evaluate("1.5e3 + .5"), Value::Float(1500.5));
+    assert_eq!(evaluate("1.5e3 - .5"), Value::Float(1499.5));
+    assert_eq!(evaluate("1 = 1.0 and 2 not= 3"), Value::Bool(true));
+    assert_eq!(
+        evaluate("\"ab\" * 2 + \"c\""),
+        Value::Str(String::from("ababc"))
+    );
+    assert_eq!(
+        evaluate("\"apple\" < \"banana\" and \"x\" == \"x\""),
+        Value::Bool(true)
+    );
+    assert_eq!(
+        evaluate("[1, 2.5, \"a\", [true]]").to_string(),
+        "[1, 2.5, \"a\", [true]]"
+    );
+    assert_eq!(evaluate("type([])"), Value::Str(String::from("array")));
+    assert_eq!(evaluate("print(1, \"two\")"), Value::Null);
+    assert_eq!(
+        evaluate("[\"two words\", 'tab\\t', r\"multi\nline\"]"),
+        Value::array(vec![
+            Value::Str(String::from("two words")),
+            Value::Str(String::from("tab\t")),
+            Value::Str(String::from("multi\nline")),
+        ])
+    );
+}
+
+#[test]
+pub fn test_integer_overflow() {
+    assert_eq!(
+        evaluate("9223372036854775807 + 1").to_string(),
+        "9223372036854775808"
+    );
+    assert_eq!(
+        evaluate("3037000500 * 3037000500 * 4").to_string(),
+        "36893488148001000000"
+    );
+    assert_eq!(evaluate("-9223372036854775807 - 1"), Value::Int(i64::MIN));
+    assert_eq!(
+        evaluate("-(-9223372036854775807 - 1)").to_string(),
+        "9223372036854775808"
+    );
+    assert_eq!(
+        evaluate("(9223372036854775807 + 10) - 10"),
+        Value::Int(i64::MAX)
+    );
+    assert_eq!(
+        evaluate("9223372036854775807 + 1 > 9223372036854775807"),
+        Value::Bool(true)
+    );
+    assert_eq!(
+        evaluate("9007199254740993 > 9007199254740992"),
+        Value::Bool(true)
+    );
+    assert_eq!(
+        evaluate("type(99999999999999999999)"),
+        Value::Str(String::from("int"))
+    );
+
+    let strict_err = |source: &str| {
+        let mut interpreter = Interpreter::new().strict();
+        match interpreter.run(&parse(&tokenize(source).unwrap()).unwrap()) {
+            Err(LetscriptError::Runtime { message, .. }) => message,
+            other => panic!("expected a runtime error, got {:?}", other),
+        }
+    };
+    assert!(strict_err("var n is 9223372036854775807 * 2").starts_with("integer overflow"));
+    assert!(strict_err("var n is -(-9223372036854775807 - 1)").starts_with("integer overflow"));
+    assert!(strict_err("var n is 3 ** 40").starts_with("integer overflow"));
+
+    for source in ["var n is 3 ** 4000000000", "var n is 99999999999 ** 200000"] {
+        assert!(matches!(
+            run_err(source),
+            LetscriptError::Runtime { message, .. } if message.ends_with("is too large")
+        ));
+    }
+    assert_eq!(evaluate("1 ** 4000000000"), Value::Int(1));
+    assert_eq!(evaluate("(-1) ** 4000000001"), Value::Int(-1));
+}
+
+#[test]
+pub fn test_division_by_zero() {
+    let err = run_err("var n is 1\nvar ratio is 5 / (n - 1)\n");
+    assert!(matches!(
+        &err,
+        LetscriptError::Runtime { message, .. } if message.starts_with("integer division by zero")
+    ));
+    assert_eq!((err.span().line, err.span().column), (2, 14));
+    assert!(matches!(
+        run_err("var r is 7 mod 0"),
+        LetscriptError::Runtime { .. }
+    ));
+    assert!(matches!(
+        run_err("var r is 99999999999999999999 / 0"),
+        LetscriptError::Runtime { .. }
+    ));
+    assert_eq!(
+        evaluate("(-9223372036854775807 - 1) / -1").to_string(),
+        "9223372036854775808"
+    );
+
+    assert_eq!(evaluate("5 / 0.0"), Value::Float(f64::INFINITY));
+    assert_eq!(evaluate("-5 / 0.0"), Value::Float(f64::NEG_INFINITY));
+    assert_eq!(evaluate("5.5 / 0"), Value::Float(f64::INFINITY));
+    assert_eq!(evaluate("0 / 0.0").to_string(), "NaN");
+    assert_eq!(evaluate("5 mod 0.0").to_string(), "NaN");
+}
+
+#[test]
+pub fn test_equality_and_identity() {
+    assert_eq!(evaluate("[1, [2, 3]] = [1.0, [2, 3]]"), Value::Bool(true));
+    assert_eq!(evaluate("[1, 2] = [2, 1]"), Value::Bool(false));
+    assert_eq!(evaluate("[1, 2] = [1, 2, 3]"), Value::Bool(false));
+    assert_eq!(
+        evaluate("{ a: 1, b: [\"x\"] } == { b: [\"x\"], a: 1.0 }"),
+        Value::Bool(true)
+    );
+    assert_eq!(evaluate("{ a: 1 } not= { a: 1, b: 2 }"), Value::Bool(true));
+    assert_eq!(
+        evaluate("{ a: null } = { a: undefined }"),
+        Value::Bool(false)
+    );
+    assert_eq!(evaluate("1.0 in [0, 1]"), Value::Bool(true));
+
+    let interpreter = run(
+        "var a is [1, { b: 2 }]\nvar alias is a\nvar copy is [1, { b: 2 }]\n\
+                           var same is a is alias\nvar other is a is copy\nvar equal is a = copy\n\
+                           function f() { }\nvar g is function () { }\nvar h is function () { }\n\
+                           var functions is [f is f, f = f, g = h, g is h]\n",
+    );
+    assert_eq!(interpreter.lookup("same"), Some(Value::Bool(true)));
+    assert_eq!(interpreter.lookup("other"), Some(Value::Bool(false)));
+    assert_eq!(interpreter.lookup("equal"), Some(Value::Bool(true)));
+    assert_eq!(
+        interpreter.lookup("functions").unwrap().to_string(),
+        "[true, true, false, false]"
+    );
+
+    assert_eq!(evaluate("1 is 1"), Value::Bool(true));
+    assert_eq!(evaluate("1 is 1.0"), Value::Bool(false));
+    assert_eq!(evaluate("\"x\" is \"x\""), Value::Bool(true));
+    assert_eq!(evaluate("[] is []"), Value::Bool(false));
+    assert_eq!(
+        evaluate("[null = null, undefined = undefined, null is null]").to_string(),
+        "[true, true, true]"
+    );
+    assert_eq!(
+        evaluate("[null = undefined, null = 0, undefined = false, null = \"\"]").to_string(),
+        "[false, false, false, false]"
+    );
+}
+
+#[test]
+pub fn test_arrays() {
+    assert_eq!(
+        evaluate("[1 + 1, [\"a\" * 2, [-3]], type(1)]").to_string(),
+        "[2, [\"aa\", [-3]], \"int\"]"
+    );
+    assert_eq!(evaluate("[10, 20, 30][0]"), Value::Int(10));
+    assert_eq!(evaluate("[10, 20, 30][-1]"), Value::Int(30));
+    assert_eq!(evaluate("[[1, 2], [3, 4]][1][-2]"), Value::Int(3));
+    assert_eq!(evaluate("\"héllo\"[1]"), Value::Str(String::from("é")));
+    assert_eq!(evaluate("[1, 2, 3, 4][1:3]").to_string(), "[2, 3]");
+    assert_eq!(evaluate("[1, 2, 3, 4][:-1]").to_string(), "[1, 2, 3]");
+    assert_eq!(evaluate("[1, 2, 3, 4][-2:]").to_string(), "[3, 4]");
+    assert_eq!(evaluate("[1, 2, 3, 4][3:1]").to_string(), "[]");
+    assert_eq!(evaluate("[1, 2, 3, 4][-10:10]").to_string(), "[1, 2, 3, 4]");
+    assert_eq!(
+        evaluate("\"letscript\"[3:]"),
+        Value::Str(String::from("script"))
+    );
+
+    let interpreter = run(
+        "var grid is [[0, 0], [0, 0]]\nvar alias is grid\nvar copy is grid[:]\n\
+                           grid[0][1] = 5\ngrid[-1] = [7]\nvar i is 0\ngrid[i] = [grid[i][1], 9]\n",
+    );
+    assert_eq!(
+        interpreter.lookup("alias").unwrap().to_string(),
+        "[[5, 9], [7]]"
+    );
+    assert_eq!(
+        interpreter.lookup("copy").unwrap().to_string(),
+        "[[0, 5], [0, 0]]"
+    );
+
+    let interpreter = run("var a is [1]\na[0] = a\nvar b is [1]\nb[0] = b\nvar same is a = b\n");
+    assert_eq!(interpreter.lookup("a").unwrap().to_string(), "[[...]]");
+    assert_eq!(interpreter.lookup("same"), Some(Value::Bool(true)));
+}
+
+#[test]
+pub fn test_array_errors() {
+    let err = run_err("var items is [1, 2, 3]\nvar x is items[3]\n");
+    assert!(matches!(
+        &err,
+        LetscriptError::Runtime { message, .. } if message == "index 3 is out of bounds for length 3"
+    ));
+    assert_eq!((err.span().line, err.span().column), (2, 10));
+    assert!(matches!(
+        run_err("var x is [1][-2]"),
+        LetscriptError::Runtime { .. }
+    ));
+    assert!(matches!(
+        run_err("var x is [][99999999999999999999]"),
+        LetscriptError::Runtime { .. }
+    ));
+    assert!(matches!(
+        run_err("var items is [1]\nitems[1] = 2\n"),
+        LetscriptError::Runtime { .. }
+    ));
+    assert!(matches!(
+        run_err("var x is [1][\"0\"]"),
+        LetscriptError::Type { .. }
+    ));
+    assert!(matches!(
+        run_err("var x is [1][0.0]"),
+        LetscriptError::Type { .. }
+    ));
+    assert!(matches!(
+        run_err("var x is [1][null:]"),
+        LetscriptError::Type { .. }
+    ));
+    assert!(matches!(
+        run_err("var x is 5[0]"),
+        LetscriptError::Type { .. }
+    ));
+    assert!(matches!(
+        run_err("var s is \"abc\"\ns[0] = \"x\"\n"),
+        LetscriptError::Type { .. }
+    ));
+}
+
+#[test]
+pub fn test_objects() {
+    let interpreter = run(
+        "var base is 40\nvar person is { name: \"Ann\", \"age\": base + 2, nested: { a: [1, 2] } }\n\
+         var name is person.name\nvar age is person[\"age\"]\nvar second is person.nested.a[1]\n\
+         var alias is person\nperson.name = \"Bob\"\nperson[\"city\"] = \"Oslo\"\n\
+         person.nested.a[0] = 0\nvar had is has(person, \"age\")\nvar removed is delete(person, \"age\")\n\
+         var again is delete(person, \"age\")\nvar has_age is has(person, \"age\")\n",
+    );
+    let lookup = |name: &str| interpreter.lookup(name).unwrap();
+    assert_eq!(lookup("name"), Value::Str(String::from("Ann")));
+    assert_eq!(lookup("age"), Value::Int(42));
+    assert_eq!(lookup("second"), Value::Int(2));
+    assert_eq!(
+        lookup("alias").to_string(),
+        "{city: \"Oslo\", name: \"Bob\", nested: {a: [0, 2]}}"
+    );
+    assert_eq!(
+        [
+            lookup("had"),
+            lookup("removed"),
+            lookup("again"),
+            lookup("has_age")
+        ],
+        [
+            Value::Bool(true),
+            Value::Bool(true),
+            Value::Bool(false),
+            Value::Bool(false)
+        ]
+    );
+    assert_eq!(evaluate("{ a: 1, a: 2 }.a"), Value::Int(2));
+    assert_eq!(evaluate("{}").to_string(), "{}");
+
+    let err = run_err("var point is { x: 1 }\nvar y is point.y\n");
+    assert!(matches!(
+        &err,
+        LetscriptError::Runtime { message, .. } if message == "the object has no field 'y'"
+    ));
+    assert_eq!((err.span().line, err.span().column), (2, 10));
+    assert!(matches!(
+        run_err("var x is { a: 1 }[0]"),
+        LetscriptError::Type { .. }
+    ));
+    assert!(matches!(
+        run_err("var x is [1].a"),
+        LetscriptError::Type { .. }
+    ));
+    assert!(matches!(
+        run_err("var n is 1\nn.a = 2\n"),
+        LetscriptError::Type { .. }
+    ));
+    assert!(matches!(
+        run_err("var x is has([1], \"a\")"),
+        LetscriptError::Type { .. }
+    ));
+    assert!(matches!(
+        run_err("var x is delete({ a: 1 }, 1)"),
+        LetscriptError::Type { .. }
+    ));
+}
+
+#[test]
+pub fn test_conditionals() {
+    let classify = |n: i32| {
+        let source = format!(
+            "var n is {}\nvar size is 0\nif n > 100 {{\n  size = 3\n}} else if n > 10 {{\n  size = 2\n}}\n\
+             else if n > 0 {{ size = 1 }} else {{\n  size = -1\n}}\n",
+            n
+        );
+        run(&source).lookup("size").unwrap()
+    };
+    assert_eq!(classify(500), Value::Int(3));
+    assert_eq!(classify(50), Value::Int(2));
+    assert_eq!(classify(5), Value::Int(1));
+    assert_eq!(classify(-5), Value::Int(-1));
+
+    let interpreter = run("var hit is false\nif 5 = 5 { hit = true }\nif false { hit = false }\n");
+    assert_eq!(interpreter.lookup("hit"), Some(Value::Bool(true)));
+}
+
+#[test]
+pub fn test_condition_truthiness() {
+    let truthy = |condition: &str| {
+        let source = format!("var taken is false\nif {} {{ taken = true }}", condition);
+        run(&source).lookup("taken") == Some(Value::Bool(true))
+    };
+    assert!(truthy("1"));
+    assert!(truthy("-2.5"));
+    assert!(!truthy("0"));
+    assert!(!truthy("0.0"));
+    assert!(!truthy("null"));
+    assert!(truthy("not 0"));
+    assert!(truthy("3 and 1"));
+
+    let err = run_err("if \"yes\" { }");
+    assert!(matches!(err, LetscriptError::Type { .. }));
+    assert_eq!(err.span().column, 4);
+    assert!(matches!(
+        run_err("if undefined { }"),
+        LetscriptError::Type { .. }
+    ));
+}
+
+#[test]
+pub fn test_logical_short_circuit() {
+    let interpreter = run("var o is {}\nvar guarded is has(o, \"k\") and o.k > 1\n\
+         var items is [1]\nvar i is 1\nvar fallback is i >= 1 or items[i] = 0\n");
+    assert_eq!(interpreter.lookup("guarded"), Some(Value::Bool(false)));
+    assert_eq!(interpreter.lookup("fallback"), Some(Value::Bool(true)));
+    assert_eq!(evaluate("0 and \"skipped\""), Value::Bool(false));
+
+    assert!(matches!(
+        run_err("var o is {}\nvar x is 1 and o.k"),
+        LetscriptError::Runtime { .. }
+    ));
+    assert!(matches!(
+        run_err("var x is 0 or \"text\""),
+        LetscriptError::Type { .. }
+    ));
+}
+
+#[test]
+pub fn test_loops() {
+    let interpreter = run("var n is 0\nloop {\n  n = n + 1\n  if n >= 4 { break }\n}\n\
+         var odd is 0\nfor (var i is 0, i < 10, i = i + 1) {\n  if i mod 2 = 0 { continue }\n  odd = odd + i\n}\n\
+         var w is 0\nwhile true {\n  w = w + 1\n  if w < 3 {\n    continue\n  }\n  break\n}\n");
+    assert_eq!(interpreter.lookup("n"), Some(Value::Int(4)));
+    assert_eq!(interpreter.lookup("odd"), Some(Value::Int(25)));
+    assert_eq!(interpreter.lookup("w"), Some(Value::Int(3)));
+}
+
+#[test]
+pub fn test_labelled_loops() {
+    let interpreter = run(
+        "var pairs is 0\nouter: for (var a is 0, a < 5, a = a + 1) {\n\
+         each b in [0, 1, 2, 3] {\n    if b > a { continue outer }\n    if a = 4 { break outer }\n\
+             pairs = pairs + 1\n  }\n}\n\
+         var spins is 0\ntwirl: loop {\n  loop {\n    spins = spins + 1\n    break twirl\n  }\n}\n",
+    );
+    assert_eq!(interpreter.lookup("pairs"), Some(Value::Int(10)));
+    assert_eq!(interpreter.lookup("spins"), Some(Value::Int(1)));
+
+    let err = run_err("while true {\n  break nowhere\n}\n");
+    assert!(matches!(err, LetscriptError::Runtime { .. }));
+    assert_eq!(err.span().line, 2);
+    assert!(matches!(
+        run_err("continue"),
+        LetscriptError::Runtime { .. }
+    ));
+    assert!(matches!(
+        parse(&tokenize("here: var x is 1").unwrap()),
+        Err(LetscriptError::Parse { .. })
+    ));
+}
+
+#[test]
+pub fn test_each_iteration() {
+    let interpreter = run(
+        "var sum is 0\nvar weighted is 0\neach n in [1, 2, 3] {\n  sum = sum + n\n}\n\
+         each i, n in [5, 6, 7] {\n  weighted = weighted + i * n\n}\n\
+         var keys is []\nvar total is 0\nvar ages is { bob: 30, al: 40 }\n\
+         each k in ages { keys = [keys, k] }\neach k, age in ages { total = total + age }\n\
+         var letters is 0\neach c in \"abc\" {\n  if c in \"cab\" { letters = letters + 1 }\n}\n",
+    );
+    assert_eq!(interpreter.lookup("sum"), Some(Value::Int(6)));
+    assert_eq!(interpreter.lookup("weighted"), Some(Value::Int(20)));
+    assert_eq!(interpreter.lookup("total"), Some(Value::Int(70)));
+    assert_eq!(interpreter.lookup("letters"), Some(Value::Int(3)));
+    assert_eq!(
+        interpreter.lookup("keys").unwrap().to_string(),
+        "[[[], \"al\"], \"bob\"]"
+    );
+    assert!(matches!(
+        run_err("each c in \"ab\" {\n  var d is c + 1\n}"),
+        LetscriptError::Type { .. }
+    ));
+    assert!(matches!(
+        run_err("each x in 5 { }"),
+        LetscriptError::Type { .. }
+    ));
+}
+
+#[test]
+pub fn test_membership() {
+    assert_eq!(evaluate("2 in [1, 2, 3]"), Value::Bool(true));
+    assert_eq!(evaluate("[2] in [1, [2]]"), Value::Bool(true));
+    assert_eq!(evaluate("4 in []"), Value::Bool(false));
+    assert_eq!(evaluate("\"bob\" in { bob: 1 }"), Value::Bool(true));
+    assert_eq!(evaluate("\"ell\" in \"hello\""), Value::Bool(true));
+    assert_eq!(evaluate("not (\"z\" in \"hello\")"), Value::Bool(true));
+    assert!(matches!(
+        run_err("var bad is 1 in 2"),
+        LetscriptError::Type { .. }
+    ));
+}
+
+#[test]
+pub fn test_functions() {
+    let interpreter = run("function fib(n) {\n  if n < 2 {\n    return n\n  }\n  return fib(n - 1) + fib(n - 2)\n}\n\
+         function add(a, b) {\n  return a + b\n}\n\
+         function nothing() {\n  var local is 1\n}\n\
+         function first_even(items) {\n  each item in items {\n    if item mod 2 = 0 { return item }\n  }\n  return\n}\n\
+         var f is fib(15)\nvar sum is add(add(1, 2), 3)\nvar empty is nothing()\n\
+         var even is first_even([1, 3, 4, 5])\nvar none is first_even([1])\n");
+    assert_eq!(interpreter.lookup("f"), Some(Value::Int(610)));
+    assert_eq!(interpreter.lookup("sum"), Some(Value::Int(6)));
+    assert_eq!(interpreter.lookup("empty"), Some(Value::Null));
+    assert_eq!(interpreter.lookup("even"), Some(Value::Int(4)));
+    assert_eq!(interpreter.lookup("none"), Some(Value::Null));
+    assert_eq!(interpreter.lookup("local"), None);
+    assert_eq!(
+        interpreter.lookup("add").unwrap().to_string(),
+        "<function add>"
+    );
+}
+
+#[test]
+pub fn test_function_errors() {
+    let err = run_err("function add(a, b) {\n  return a + b\n}\nvar x is add(1)\n");
+    assert!(
+        matches!(err, LetscriptError::Runtime { ref message, .. } if message.contains("expects 2"))
+    );
+    assert_eq!((err.span().line, err.span().column), (4, 10));
+
+    // A function sees globals and its parameters, not its caller's locals.
+    let err = run_err("function peek() {\n  return hidden\n}\n{\n  var hidden is 1\n  peek()\n}\n");
+    assert_eq!(err, LetscriptError::name(err.span(), "hidden"));
+
+    assert!(matches!(
+        run_err("return 1"),
+        LetscriptError::Runtime { .. }
+    ));
+    assert!(matches!(
+        run_err("function escape() {\n  break\n}\nescape()\n"),
+        LetscriptError::Runtime { .. }
+    ));
+    let err = run_err("function down(n) {\n  return down(n + 1)\n}\ndown(0)\n");
+    assert!(
+        matches!(err, LetscriptError::Runtime { ref message, .. } if message.contains("call depth"))
+    );
+}
+
+#[test]
+pub fn test_closures() {
+    let interpreter = run("function counter() {\n  var count is 0\n  return function () {\n    count = count + 1\n    return count\n  }\n}\n\
+         var tick is counter()\nvar other is counter()\ntick()\ntick()\nvar ticks is tick()\nvar others is other()\n\
+         function apply(f, x) {\n  return f(x)\n}\nvar offset is 10\n\
+         var shifted is apply(function (n) { return n + offset }, 5)\n\
+         var total is 0\neach f in [function (n) { return n * 2 }, function (n) { return n * 3 }] {\n  total = total + f(1)\n}\n\
+         var ops is { twice: function (n) { return n * 2 } }\n");
+    assert_eq!(interpreter.lookup("ticks"), Some(Value::Int(3)));
+    assert_eq!(interpreter.lookup("others"), Some(Value::Int(1)));
+    assert_eq!(interpreter.lookup("shifted"), Some(Value::Int(15)));
+    assert_eq!(interpreter.lookup("total"), Some(Value::Int(5)));
+    assert_eq!(
+        interpreter.lookup("ops").unwrap().to_string(),
+        "{twice: <function>}"
+    );
+    assert_eq!(interpreter.lookup("count"), None);
+
+    let interpreter = run("function counter() {\n  var count is 0\n  function tick() {\n    count = count + 1\n    return count\n  }\n  return tick\n}\n\
+         var tick is counter()\ntick()\nvar ticks is tick()\n\
+         function outer() {\n  function helper() { return 42 }\n  return function () { return helper }\n}\n\
+         var helped is outer()()()\n");
+    assert_eq!(interpreter.lookup("ticks"), Some(Value::Int(2)));
+    assert_eq!(interpreter.lookup("helped"), Some(Value::Int(42)));
+}
+
+#[test]
+pub fn test_functions_declared_in_loops_are_freed() {
+    let mut interpreter = run("var items is [[1], [2], [3]]\n");
+    let elements: Vec<_> = match &interpreter.lookup("items") {
+        Some(Value::Array(items)) => items
+            .borrow()
+            .iter()
+            .map(|item| match item {
+                Value::Array(item) => Rc::downgrade(item),
+                other => panic!("expected an array, got {:?}", other),
+            })
+            .collect(),
+        other => panic!("expected an array, got {:?}", other),
+    };
+    let source = "each item in items {\n  function first() { return item[0] }\n\
+                  var again is function () { return first() }\n  again()\n}\nitems = null\n";
+    interpreter
+        .run(&parse(&tokenize(source).unwrap()).unwrap())
+        .unwrap();
+    // Each iteration's frame, and the `item` it holds, is freed with the
+    // functions declared in it.
+    assert!(elements.iter().all(|element| element.upgrade().is_none()));
+}
+
+#[test]
+pub fn test_nested_function_recursion() {
+    let interpreter = run("var result is 0\n{\n  function fact(n) {\n    if n <= 1 { return 1 }\n    return n * fact(n - 1)\n  }\n  result = fact(5)\n}\n\
+         var late is function () { return later }\nvar later is 7\nvar seen is late()\n");
+    assert_eq!(interpreter.lookup("result"), Some(Value::Int(120)));
+    assert_eq!(interpreter.lookup("seen"), Some(Value::Int(7)));
+    assert!(matches!(
+        run_err("var f is function (a) { return a }\nf()"),
+        LetscriptError::Runtime { ref message, .. } if message.contains("anonymous function")
+    ));
+}
+
+#[test]
+pub fn test_deeply_nested_expressions() {
+    // Deep enough to overflow the native stack if parsing, evaluating,
+    // displaying or dropping recursed without growing it.
+    const DEPTH: usize = 100_000;
+    let nested = format!("{}{}", "[".repeat(DEPTH), "]".repeat(DEPTH));
+    assert_eq!(evaluate(&nested).to_string(), nested);
+    let built = run(&format!(
+        "var a is []\nfor (var i is 1, i < {}, i = i + 1) {{\n  a = [a]\n}}\n",
+        DEPTH
+    ));
+    assert_eq!(built.lookup("a").unwrap().to_string(), nested);
+    let sum = format!("0{}", " + 1".repeat(DEPTH));
+    assert_eq!(evaluate(&sum), Value::Int(DEPTH as i64));
+    let powers = format!("1{}", " ** 1".repeat(DEPTH));
+    assert_eq!(evaluate(&powers), Value::Int(1));
+    let negations = format!("{}true", "not ".repeat(DEPTH));
+    assert_eq!(evaluate(&negations), Value::Bool(true));
+    let parenthesized = format!("{}1{}", "(".repeat(DEPTH), ")".repeat(DEPTH));
+    assert_eq!(evaluate(&parenthesized), Value::Int(1));
+    let blocks = format!(
+        "{}var inner is 1{}",
+        "{\n".repeat(DEPTH),
+        "\n}".repeat(DEPTH)
+    );
+    run(&blocks);
+}
+
+#[test]
+pub fn test_string_interpolation() {
+    let interpreter = run("var name is \"Ann\"\nvar count is 2\n\
+         var message is \"Hello ${name}, you have ${count + 1} items\"\n\
+         var nested is '${ [count, \"${name}!\"] } ${ {n: 1.5} } ${null}'\n\
+         var escaped is \"\\${name} costs $5\"\n");
+    assert_eq!(
+        interpreter.lookup("message"),
+        Some(Value::Str(String::from("Hello Ann, you have 3 items")))
+    );
+    assert_eq!(
+        interpreter.lookup("nested"),
+        Some(Value::Str(String::from("[2, \"Ann!\"] {n: 1.5} null")))
+    );
+    assert_eq!(
+        interpreter.lookup("escaped"),
+        Some(Value::Str(String::from("${name} costs $5")))
+    );
+
+    let err = run_err("var total is 1\nprint(\"sum: ${total + missing}\")");
+    assert_eq!(err, LetscriptError::name(err.span(), "missing"));
+    assert_eq!((err.span().line, err.span().column), (2, 23));
+}
+
+#[test]
+pub fn test_identifier_names() {
+    let interpreter = run(
+        "var Total is 1\nvar _tmp is 2\nvar item2 is 3\nvar island is 4\nvar spin is 5\n\
+         var format is Total + _tmp + item2 + island + spin\n",
+    );
+    assert_eq!(interpreter.lookup("format"), Some(Value::Int(15)));
+    assert!(matches!(
+        parse(&tokenize("var while is 1").unwrap()).unwrap_err(),
+        LetscriptError::Parse { .. }
+    ));
+    assert!(matches!(
+        parse(&tokenize("var true is 1").unwrap()).unwrap_err(),
+        LetscriptError::Parse { ref message, .. } if message == "'true' is not a valid name"
+    ));
+}
@@ -1,253 +1,226 @@
-use crate::parser::collect_expression_tokens;
-use crate::parser::eval_expression;
-use crate::parser::infix_to_postfix;
-use crate::parser::parse_logical_iter;
-use crate::parser::resolve_unary_operators;
+use crate::ast::{Expr, Stmt};
+use crate::error::LetscriptError;
+use crate::parser::parse;
+use crate::parser::parse_expression;
 use crate::token::TokenType;
 use crate::tokenizer::tokenize;
-
-#[test]
-pub fn test_collect_expressions() {
-    let mut input = String::from("3+ 4 + (6- 5) \n");
-    let tokens = tokenize(&mut input);
-    let current = tokens.iter().next().unwrap();
-    let collected = collect_expression_tokens(current, &mut tokens.iter());
-    assert_eq!(collected.1.len(), 9);
-}
-
-#[test]
-pub fn test_resolve_unary_operators() {
-    let mut input = String::from("3 + -5");
-    let tokens = tokenize(&mut input);
-    let result = resolve_unary_operators(tokens);
-    assert_eq!(result.len(), 3);
-    assert_eq!(result[1].tok_type, TokenType::Plus)
-}
-
-#[test]
-pub fn test_resolve_unary_operators_1() {
-    let mut input = String::from("not true");
-    let tokens = tokenize(&mut input);
-    let result = resolve_unary_operators(tokens);
-    
-    assert_eq!(result.len(), 1);
-    assert_eq!(result[0].tok_type, TokenType::Literal);
-    assert_eq!(result[0].clone().tok_value.unwrap().s_val.unwrap(), "false");
-}
-
-#[test]
-pub fn test_resolve_unary_operators_2() {
-    let mut input = String::from("-5");
-    let tokens = tokenize(&mut input);
-    let result = resolve_unary_operators(tokens);
-    assert_eq!("-5", result[0].clone().tok_value.unwrap().s_val.unwrap());
-    assert_eq!(result.len(), 1);
-    assert_eq!(result[0].tok_type, TokenType::Literal);
-    
-}
-
-#[test]
-pub fn test_resolve_unary_operators_3() {
-    let mut input = String::from("- 2");
-    let tokens = tokenize(&mut input);
-    let result = resolve_unary_operators(tokens);
-    assert_eq!("-2", result[0].clone().tok_value.unwrap().s_val.unwrap());
-    assert_eq!(result.len(), 1);
-    assert_eq!(result[0].tok_type, TokenType::Literal);
-    
-}
-
-pub fn test_resolve_unary_operators_4() {
-    let mut input = String::from(" x not= 2");
-    let tokens = tokenize(&mut input);
-    let result = resolve_unary_operators(tokens);
-    assert_eq!(result.len(), 3);
-    assert_eq!(result[1].tok_type, TokenType::NotEqual);
-    
-}
-
-
-
-
-#[test]
-pub fn test_infix_to_postfix_1() {
-    let mut input = String::from("5+ 7 or 3 * 5");
-    let tokens = tokenize(&mut input);
-    let postfix = infix_to_postfix(tokens);
-
-    postfix.iter().for_each(|t| {
-        if t.tok_type == TokenType::Literal {
-            println!("Token({})", t.tok_value.clone().unwrap().s_val.unwrap())
-        } else {
-            println!("Token({:?})", t.tok_type);
-        }
-    });
-    assert_eq!(postfix.len(), 7);
-}
-
-#[test]
-pub fn test_infix_to_postfix_2() {
-    let mut input = String::from("(3 - 5) * 12");
-    let tokens = tokenize(&mut input);
-    let postfix = infix_to_postfix(tokens);
-
-    postfix.iter().for_each(|t| {
-        if t.tok_type == TokenType::Literal {
-            println!("Token({})", t.tok_value.clone().unwrap().s_val.unwrap())
-        } else {
-            println!("Token({:?})", t.tok_type);
-        }
-    });
-    assert_eq!(postfix.len(), 5);
-}
-
-#[test]
-pub fn test_eval_postfix_1() {
-    let mut input = String::from("5+ 7 or 3 * 5");
-    let tokens = tokenize(&mut input);
-    let mut postfix = infix_to_postfix(tokens);
-    let evaled = eval_expression(&mut postfix);
-    postfix.iter().for_each(|t| {
-        if t.tok_type == TokenType::Literal {
-            println!("Token({})", t.tok_value.clone().unwrap().s_val.unwrap())
-        } else {
-            println!("Token({:?})", t.tok_type);
-        }
-    });
-
-    assert_eq!(evaled.tok_value.unwrap().s_val.unwrap(), "true");
-}
-
-#[test]
-pub fn test_eval_postfix_2() {
-    let mut input = String::from("(3 - 5) * 12");
-    let tokens = tokenize(&mut input);
-    let mut postfix = infix_to_postfix(tokens);
-    let evaled = eval_expression(&mut postfix);
-    postfix.iter().for_each(|t| {
-        if t.tok_type == TokenType::Literal {
-            println!("Token({})", t.tok_value.clone().unwrap().s_val.unwrap())
-        } else {
-            println!("Token({:?})", t.tok_type);
+use crate::value::Value;
+
+#[test]
+pub fn test_parse_expression_stops_at_separators() {
+    let input = String::from("3+ 4 + (6- 5) \n");
+    let tokens = tokenize(&input).unwrap();
+    let mut iter = tokens.iter().peekable();
+    assert!(matches!(
+        parse_expression(&mut iter),
+        Ok(Expr::Binary {
+            op: TokenType::Plus,
+            ..
+        })
+    ));
+    assert_eq!(iter.next().unwrap().tok_type, TokenType::NewLine);
+
+    let tokens = tokenize("limit, i + 1) {").unwrap();
+    let mut iter = tokens.iter().peekable();
+    assert!(matches!(
+        parse_expression(&mut iter),
+        Ok(Expr::Variable { .. })
+    ));
+    assert_eq!(iter.next().unwrap().tok_type, TokenType::Comma);
+    parse_expression(&mut iter).unwrap();
+    assert_eq!(iter.next().unwrap().tok_type, TokenType::CloseParen);
+}
+
+#[test]
+pub fn test_parse_program() {
+    let input = String::from("var first is 3 + 4\n\tvar second is (first * 2) mod 5\r\n");
+    let tokens = tokenize(&input).unwrap();
+    let program = parse(&tokens).unwrap();
+    assert_eq!(program.body.len(), 2);
+    match &program.body[1] {
+        Stmt::VarDecl { name, value, span } => {
+            assert_eq!(name, "second");
+            assert!(matches!(
+                value,
+                Expr::Binary {
+                    op: TokenType::Mod,
+                    ..
+                }
+            ));
+            assert_eq!((span.line, span.column), (2, 2));
         }
-    });
-
-    assert_eq!(evaled.tok_value.unwrap().s_val.unwrap(), "-24");
-}
-
-#[test]
-pub fn test_eval_postfix_3() {
-    let mut input = String::from("(3 * 13) mod 12");
-    let tokens = tokenize(&mut input);
-    let mut postfix = infix_to_postfix(tokens);
-    let evaled = eval_expression(&mut postfix);
-    postfix.iter().for_each(|t| {
-        if t.tok_type == TokenType::Literal {
-            println!("Token({})", t.tok_value.clone().unwrap().s_val.unwrap())
-        } else {
-            println!("Token({:?})", t.tok_type);
-        }
-    });
-
-    assert_eq!(evaled.tok_value.unwrap().s_val.unwrap(), "3");
-}
-
-#[test]
-pub fn test_eval_postfix_4() {
-    let mut input = String::from("(42.6 /6) + 1");
-    let tokens = tokenize(&mut input);
-    let mut postfix = infix_to_postfix(tokens);
-    let evaled = eval_expression(&mut postfix);
-    postfix.iter().for_each(|t| {
-        if t.tok_type == TokenType::Literal {
-            println!("Token({})", t.tok_value.clone().unwrap().s_val.unwrap())
-        } else {
-            println!("Token({:?})", t.tok_type);
-        }
-    });
-
+        other => panic!("expected a declaration, got {:?}", other),
+    }
+}
+
+#[test]
+pub fn test_parse_statements() {
+    let input = "if x { y } else if z {\n} else {\n  var w is 1\n}\nwhile x { }\nloop {\n}\n\
+                 for (var i is 0, i < 3, i) { }\neach item in items { }\n\
+                 function add(a, b) {\n  return a + b\n}\n{ return }\n";
+    let program = parse(&tokenize(input).unwrap()).unwrap();
+    let kinds: Vec<&str> = program
+        .body
+        .iter()
+        .map(|stmt| match stmt {
+            Stmt::If { .. } => "if",
+            Stmt::While { .. } => "while",
+            Stmt::Loop { .. } => "loop",
+            Stmt::For { .. } => "for",
+            Stmt::Each { .. } => "each",
+            Stmt::FunctionDecl { .. } => "function",
+            Stmt::Block(_) => "block",
+            _ => "other",
+        })
+        .collect();
     assert_eq!(
-        evaled.tok_value.unwrap().s_val.unwrap(),
-        "8.100000000000001"
+        kinds,
+        ["if", "while", "loop", "for", "each", "function", "block"]
     );
-}
-
-#[test]
-pub fn test_eval_postfix_5() {
-    let mut input = String::from("5 > 7");
-    let tokens = tokenize(&mut input);
-    let mut postfix = infix_to_postfix(tokens);
-    let evaled = eval_expression(&mut postfix);
-    postfix.iter().for_each(|t| {
-        if t.tok_type == TokenType::Literal {
-            println!("Token({})", t.tok_value.clone().unwrap().s_val.unwrap())
-        } else {
-            println!("Token({:?})", t.tok_type);
+    match &program.body[0] {
+        Stmt::If {
+            else_branch: Some(else_branch),
+            ..
+        } => assert!(matches!(
+            else_branch.body[0],
+            Stmt::If {
+                else_branch: Some(_),
+                ..
+            }
+        )),
+        other => panic!("expected an else-if chain, got {:?}", other),
+    }
+    match &program.body[5] {
+        Stmt::FunctionDecl {
+            name, params, body, ..
+        } => {
+            assert_eq!(name, "add");
+            assert_eq!(params, &["a", "b"]);
+            assert!(matches!(body.body[0], Stmt::Return { value: Some(_), .. }));
         }
-    });
-
-    assert_eq!(evaled.tok_value.unwrap().s_val.unwrap(), "false");
-}
-
-#[test]
-pub fn test_eval_postfix_6() {
-    let mut input = String::from("5 >= 5");
-    let tokens = tokenize(&mut input);
-    let mut postfix = infix_to_postfix(tokens);
-    let evaled = eval_expression(&mut postfix);
-    postfix.iter().for_each(|t| {
-        if t.tok_type == TokenType::Literal {
-            println!("Token({})", t.tok_value.clone().unwrap().s_val.unwrap())
-        } else {
-            println!("Token({:?})", t.tok_type);
+        other => panic!("expected a function, got {:?}", other),
+    }
+}
+
+#[test]
+pub fn test_parse_literals() {
+    let program = parse(&tokenize("var s is \"hi\"\nvar a is [1, [2], \"x\"]\n").unwrap()).unwrap();
+    match &program.body[0] {
+        Stmt::VarDecl { value, .. } => assert!(matches!(
+            value,
+            Expr::Literal { value: Value::Str(s), .. } if s == "hi"
+        )),
+        other => panic!("expected a declaration, got {:?}", other),
+    }
+    match &program.body[1] {
+        Stmt::VarDecl {
+            value: Expr::Array { elements, .. },
+            ..
+        } => {
+            assert_eq!(elements.len(), 3);
+            assert!(matches!(elements[1], Expr::Array { .. }));
         }
-    });
-
-    assert_eq!(evaled.tok_value.unwrap().s_val.unwrap(), "true");
+        other => panic!("expected an array, got {:?}", other),
+    }
 }
 
 #[test]
-pub fn test_eval_postfix_7() {
-    let mut input = String::from("5 = 5 and 3 = 2");
-    let tokens = tokenize(&mut input);
-    let mut postfix = infix_to_postfix(tokens);
-    let evaled = eval_expression(&mut postfix);
-    postfix.iter().for_each(|t| {
-        if t.tok_type == TokenType::Literal {
-            println!("Token({})", t.tok_value.clone().unwrap().s_val.unwrap())
-        } else {
-            println!("Token({:?})", t.tok_type);
+pub fn test_parse_numbers() {
+    let number = |source: &str| {
+        let tokens = tokenize(source).unwrap();
+        match &parse_expression(&mut tokens.iter().peekable())? {
+            Expr::Literal { value, .. } => Ok(value.clone()),
+            other => panic!("expected a literal, got {:?}", other),
         }
-    });
-
-    assert_eq!(evaled.tok_value.unwrap().s_val.unwrap(), "false");
-}
-
-#[test]
-pub fn test_eval_postfix_8() {
-    let mut input = String::from("((1 +4)  = 5) and 2 = 2");
-    let tokens = tokenize(&mut input);
-    let mut postfix = infix_to_postfix(tokens);
-    let evaled = eval_expression(&mut postfix);
-    postfix.iter().for_each(|t| {
-        if t.tok_type == TokenType::Literal {
-            println!("Token({})", t.tok_value.clone().unwrap().s_val.unwrap())
-        } else {
-            println!("Token({:?})", t.tok_type);
-        }
-    });
-
-    assert_eq!(evaled.tok_value.unwrap().s_val.unwrap(), "true");
-}
-
-
-#[test]
-pub fn test_logical_expr() {
-    let mut input = String::from("((1 +4)  = 5) and 2 = 2");
-    let binding = tokenize(&mut input);
-    let mut tokens = binding.iter();
-    let mut x;
-    let mut y;
-    (x, y) = parse_logical_iter(Box::new(tokens.peekable()));
-    assert_eq!(y.next().is_none(), true);
+    };
+    assert_eq!(number("0xFF"), Ok(Value::Int(255)));
+    assert_eq!(number("0b1010"), Ok(Value::Int(10)));
+    assert_eq!(number("0o17"), Ok(Value::Int(15)));
+    assert_eq!(number("1_000_000"), Ok(Value::Int(1_000_000)));
+    assert_eq!(number("0x7fff_ffff"), Ok(Value::Int(i32::MAX as i64)));
+    assert_eq!(number("2147483648"), Ok(Value::Int(2147483648)));
+    assert_eq!(number("0x1_0000_0000"), Ok(Value::Int(1 << 32)));
+    assert_eq!(number("-0x8000_0000_0000_0000"), Ok(Value::Int(i64::MIN)));
+    assert_eq!(number("-0x10"), Ok(Value::Int(-16)));
+    assert_eq!(number("6.02e23"), Ok(Value::Float(6.02e23)));
+    assert_eq!(number("1E-3"), Ok(Value::Float(0.001)));
+    assert_eq!(number("2e3"), Ok(Value::Float(2000.0)));
+    assert_eq!(number(".5"), Ok(Value::Float(0.5)));
+    assert_eq!(number("- .25"), Ok(Value::Float(-0.25)));
+
+    let out_of_range = |source: &str| match number(source) {
+        Err(LetscriptError::Parse { message, .. }) => message,
+        other => panic!("expected an error, got {:?}", other),
+    };
+    assert_eq!(
+        number("99999999999999999999999").map(|value| value.to_string()),
+        Ok(String::from("99999999999999999999999"))
+    );
+    assert_eq!(out_of_range("1e400"), "float 1e400 is out of range");
+}
+
+#[test]
+pub fn test_parse_errors() {
+    let parse_source = |source: &str| parse(&tokenize(source).unwrap()).unwrap_err();
+
+    let err = parse_source("var known is 1\nvar other is known +\n");
+    assert_eq!((err.span().line, err.span().column), (2, 20));
+
+    let err = parse_source("var s is \"total: ${1 + * 2}\"");
+    assert_eq!((err.span().line, err.span().column), (1, 24));
+    assert!(matches!(
+        parse_source("var s is \"${}\""),
+        LetscriptError::Parse { .. }
+    ));
+    assert!(matches!(
+        parse_source("var s is \"${a b}\""),
+        LetscriptError::Parse { .. }
+    ));
+
+    assert!(matches!(
+        parse_source("var unclosed is (1 + 2"),
+        LetscriptError::Parse { .. }
+    ));
+    assert!(matches!(
+        parse_source("var missing is 1 +"),
+        LetscriptError::Parse { .. }
+    ));
+    assert!(matches!(
+        parse_source("var is is 3"),
+        LetscriptError::Parse { .. }
+    ));
+    assert!(matches!(
+        parse_source("var pair is 1 2"),
+        LetscriptError::Parse { .. }
+    ));
+    assert!(matches!(
+        parse_source("while x {\n"),
+        LetscriptError::Parse { .. }
+    ));
+    assert!(matches!(
+        parse_source("else { }"),
+        LetscriptError::Parse { .. }
+    ));
+    let err = parse_source("var i is 0\nfor (var i is 0");
+    assert_eq!((err.span().line, err.span().column), (2, 15));
+    let err = parse_source("var x is [1]\nx[");
+    assert_eq!((err.span().line, err.span().column), (2, 2));
+
+    let message = |source: &str| match parse_source(source) {
+        LetscriptError::Parse { message, .. } => message,
+        other => panic!("expected a parse error, got {:?}", other),
+    };
+    assert_eq!(message("var x is [1, 2"), "unclosed '['");
+    assert_eq!(message("var x is (1, 2)"), "expected ')', found ','");
+    assert_eq!(message("}"), "unexpected '}'");
+    assert_eq!(message("var x is (1\n2)"), "expected ')', found a newline");
+
+    assert!(matches!(
+        parse_source("for ("),
+        LetscriptError::Parse { .. }
+    ));
+    assert!(matches!(
+        parse_source("for (var i is 0, i < 1,"),
+        LetscriptError::Parse { .. }
+    ));
 }
@@ -1,13 +1,21 @@
+use std::borrow::Cow;
+use std::fmt;
+
 #[derive(Clone, Debug, Copy, PartialEq, Eq, Hash)]
 pub enum TokenType {
     Assign,
     Equals,
+    /// `==`, which always compares, where `=` at the start of a statement
+    /// assigns.
+    DoubleEquals,
     NotEqual,
     Plus,
     Minus,
     Mult,
     Div,
     Mod,
+    /// `**`, raising the left operand to the power of the right.
+    Pow,
     Not,
     And,
     Or,
@@ -16,15 +24,29 @@ pub enum TokenType {
     LessThan,
     Gte,
     Lte,
+    /// A number, `true`, `false`, `null` or `undefined`.
     Literal,
+    /// A name that is not a keyword.
+    Identifier,
     Bool,
     Function,
-    Quote,
+    Return,
+    /// A string literal; the token's value holds the decoded contents.
+    Str,
+    /// The text of an interpolated string up to its first `${`.
+    TemplateHead,
+    /// The text between a `}` and the next `${` of an interpolated string.
+    TemplateMiddle,
+    /// The text between the last `}` of an interpolated string and its
+    /// closing quote.
+    TemplateTail,
     If,
     Else,
     While,
     For,
     Loop,
+    Break,
+    Continue,
     Each,
     In,
     Period,
@@ -40,13 +62,122 @@ pub enum TokenType {
     NewLine,
     TokenError,
 }
+/// The token as written in the source, or what kind of text it is for
+/// tokens such as names and strings whose text varies.
+impl fmt::Display for TokenType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let text = match self {
+            TokenType::Assign => "var",
+            TokenType::Equals => "=",
+            TokenType::DoubleEquals => "==",
+            TokenType::NotEqual => "not=",
+            TokenType::Plus => "+",
+            TokenType::Minus => "-",
+            TokenType::Mult => "*",
+            TokenType::Div => "/",
+            TokenType::Mod => "mod",
+            TokenType::Pow => "**",
+            TokenType::Not => "not",
+            TokenType::And => "and",
+            TokenType::Or => "or",
+            TokenType::Is => "is",
+            TokenType::GreaterThan => ">",
+            TokenType::LessThan => "<",
+            TokenType::Gte => ">=",
+            TokenType::Lte => "<=",
+            TokenType::Literal => "a literal",
+            TokenType::Identifier => "a name",
+            TokenType::Bool => "a boolean",
+            TokenType::Function => "function",
+            TokenType::Return => "return",
+            TokenType::Str
+            | TokenType::TemplateHead
+            | TokenType::TemplateMiddle
+            | TokenType::TemplateTail => "a string",
+            TokenType::If => "if",
+            TokenType::Else => "else",
+            TokenType::While => "while",
+            TokenType::For => "for",
+            TokenType::Loop => "loop",
+            TokenType::Break => "break",
+            TokenType::Continue => "continue",
+            TokenType::Each => "each",
+            TokenType::In => "in",
+            TokenType::Period => ".",
+            TokenType::Colon => ":",
+            TokenType::Comma => ",",
+            TokenType::Object => "an object",
+            TokenType::OpenBrace => "{",
+            TokenType::CloseBrace => "}",
+            TokenType::OpenBracket => "[",
+            TokenType::CloseBracket => "]",
+            TokenType::OpenParen => "(",
+            TokenType::CloseParen => ")",
+            TokenType::NewLine => "a newline",
+            TokenType::TokenError => "an invalid token",
+        };
+        f.write_str(text)
+    }
+}
+
+/// The text of a literal, identifier or string token. It borrows from the
+/// source unless the lexer had to build it, as for strings with escapes.
 #[derive(Clone, Debug)]
-pub struct TokenValue {
-    pub s_val: Option<String>,
+pub struct TokenValue<'a> {
+    pub s_val: Option<Cow<'a, str>>,
+}
+
+/// Location of a token in the source text. `start` and `end` are byte
+/// offsets; `line` and `column` are 1-based and refer to `start`. A span
+/// does not name its file: a program is always a single source, so the
+/// file name is given to [`LetscriptError::report`] along with that source.
+///
+/// [`LetscriptError::report`]: crate::error::LetscriptError::report
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Span {
+    pub line: usize,
+    pub column: usize,
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    /// Returns a span covering both `self` and `other`.
+    pub fn to(&self, other: Span) -> Span {
+        let (first, last) = if self.start <= other.start {
+            (*self, other)
+        } else {
+            (other, *self)
+        };
+        Span {
+            line: first.line,
+            column: first.column,
+            start: first.start,
+            end: first.end.max(last.end),
+        }
+    }
+}
+
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.line, self.column)
+    }
+}
+
+/// Source text that is not part of the program but is kept for tools
+/// such as a doc generator or formatter. Only `///` doc comments are kept;
+/// `text` is what follows the slashes.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Trivia<'a> {
+    pub text: &'a str,
+    pub span: Span,
 }
 
 #[derive(Clone, Debug)]
-pub struct Token {
+pub struct Token<'a> {
     pub tok_type: TokenType,
-    pub tok_value: Option<TokenValue>,
+    pub tok_value: Option<TokenValue<'a>>,
+    pub span: Span,
+    /// Doc comments written on the lines before the token.
+    pub trivia: Vec<Trivia<'a>>,
 }
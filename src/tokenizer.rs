@@ -1,67 +1,219 @@
+use crate::error::LetscriptError;
+use crate::error::Result;
+use crate::token::Span;
 use crate::token::Token;
 use crate::token::TokenType;
 use crate::token::TokenValue;
-use regex::Regex;
+use crate::token::Trivia;
+use phf::phf_map;
+use std::borrow::Cow;
 use std::str::Chars;
+use unicode_xid::UnicodeXID;
 
-pub fn tokenize(program_string: &mut String) -> Vec<Token> {
+#[derive(Clone, Copy, Debug)]
+struct Position {
+    offset: usize,
+    line: usize,
+    column: usize,
+}
+
+/// Character stream that remembers where the most recently returned
+/// character sits in the source text.
+struct Cursor<'a> {
+    source: &'a str,
+    chars: Chars<'a>,
+    current: Position,
+    upcoming: Position,
+}
+
+impl<'a> Cursor<'a> {
+    fn new(text: &'a str) -> Cursor<'a> {
+        let start = Position {
+            offset: 0,
+            line: 1,
+            column: 1,
+        };
+        Cursor {
+            source: text,
+            chars: text.chars(),
+            current: start,
+            upcoming: start,
+        }
+    }
+
+    /// Position of the character last returned by `next`, or the end of
+    /// the input once the stream is exhausted.
+    fn location(&self) -> Position {
+        self.current
+    }
+
+    /// Span running from `start` to the end of the character last
+    /// returned by `next`.
+    fn span_from(&self, start: Position) -> Span {
+        Span {
+            line: start.line,
+            column: start.column,
+            start: start.offset,
+            end: self.upcoming.offset,
+        }
+    }
+
+    /// Source text from byte offset `start` to the end of the character
+    /// last returned by `next`.
+    fn slice_from(&self, start: usize) -> &'a str {
+        &self.source[start..self.upcoming.offset]
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.clone().next()
+    }
+
+    /// The character after the one `peek` returns.
+    fn peek_second(&self) -> Option<char> {
+        self.chars.clone().nth(1)
+    }
+
+    /// Whether the upcoming characters are any number of `#` followed by
+    /// a `"`, i.e. the rest of a raw string opener.
+    fn at_raw_string(&self) -> bool {
+        self.chars.clone().find(|c| *c != '#') == Some('"')
+    }
+
+    /// Consumes `count` upcoming `#` characters if they are all there.
+    fn eat_hashes(&mut self, count: usize) -> bool {
+        if self.chars.clone().take(count).filter(|c| *c == '#').count() != count {
+            return false;
+        }
+        for _ in 0..count {
+            self.next();
+        }
+        true
+    }
+}
+
+impl Iterator for Cursor<'_> {
+    type Item = char;
+
+    fn next(&mut self) -> Option<char> {
+        self.current = self.upcoming;
+        let c = self.chars.next()?;
+        self.upcoming.offset += c.len_utf8();
+        if c == '\n' {
+            self.upcoming.line += 1;
+            self.upcoming.column = 1;
+        } else {
+            self.upcoming.column += 1;
+        }
+        Some(c)
+    }
+}
+
+/// Reserved words and the tokens they produce. Any other name is an
+/// identifier; `true`, `false`, `null` and `undefined` are literals.
+static KEYWORDS: phf::Map<&'static str, TokenType> = phf_map! {
+    "var" => TokenType::Assign,
+    "is" => TokenType::Is,
+    "and" => TokenType::And,
+    "or" => TokenType::Or,
+    "not" => TokenType::Not,
+    "mod" => TokenType::Mod,
+    "in" => TokenType::In,
+    "if" => TokenType::If,
+    "else" => TokenType::Else,
+    "for" => TokenType::For,
+    "each" => TokenType::Each,
+    "while" => TokenType::While,
+    "loop" => TokenType::Loop,
+    "break" => TokenType::Break,
+    "continue" => TokenType::Continue,
+    "function" => TokenType::Function,
+    "return" => TokenType::Return,
+    "true" => TokenType::Literal,
+    "false" => TokenType::Literal,
+    "null" => TokenType::Literal,
+    "undefined" => TokenType::Literal,
+};
+
+/// How a piece of string text ended.
+enum Segment {
+    /// At the closing quote.
+    Closed,
+    /// At a `${` starting an interpolated expression.
+    Interpolated,
+}
+
+/// An interpolated string whose expressions are being lexed. `depth`
+/// counts the braces opened inside the current interpolation, so the `}`
+/// closing it can be told apart from the end of an object literal.
+struct Template {
+    quote: char,
+    open: Position,
+    interpolation: Position,
+    depth: usize,
+}
+
+// Errors for text still open at the end of the source. More source could
+// close it, unlike a string cut off by the end of its line.
+const UNTERMINATED_INTERPOLATION: &str = "unterminated interpolation, expected '}'";
+const UNTERMINATED_BLOCK_COMMENT: &str = "unterminated block comment";
+const UNTERMINATED_RAW_STRING: &str = "unterminated raw string";
+
+/// Whether `err`, returned by [`tokenize`], is an interpolation, block
+/// comment or raw string left open at the end of the source.
+pub fn is_unterminated(err: &LetscriptError) -> bool {
+    matches!(
+        err,
+        LetscriptError::Lex { message, .. }
+            if [
+                UNTERMINATED_INTERPOLATION,
+                UNTERMINATED_BLOCK_COMMENT,
+                UNTERMINATED_RAW_STRING,
+            ]
+            .contains(&message.as_str())
+    )
+}
+
+/// Splits `program_string` into tokens in a single pass. Names, numbers
+/// and strings without escapes borrow their text from `program_string`.
+pub fn tokenize(program_string: &str) -> Result<Vec<Token<'_>>> {
     let mut tokens = Vec::<Token>::new();
+    let mut templates = Vec::<Template>::new();
+    // Doc comments waiting for the token they document.
+    let mut trivia = Vec::<Trivia>::new();
 
-    let var_regx = Regex::new(r"var\z").unwrap();
-    let is_regex = Regex::new(r"is\z").unwrap();
-    let and_regex = Regex::new(r"and\z").unwrap();
-    let or_regex = Regex::new(r"or\z").unwrap();
-    let gte_regex = Regex::new(r">=\z").unwrap();
-    let lte_regex = Regex::new(r"<=\z").unwrap();
-    let not_regex = Regex::new(r"not\z").unwrap();
-    let not_eq_regex = Regex::new(r"not=\z").unwrap();
-    let if_regex = Regex::new(r"if\z").unwrap();
-    let else_regex = Regex::new(r"else\z").unwrap();
-    let for_regex = Regex::new(r"for\z").unwrap();
-    let while_regex = Regex::new(r"while\z").unwrap();
-    let each_regex = Regex::new(r"each\z").unwrap();
-    let in_regex = Regex::new(r"in\z").unwrap();
-    let loop_regex = Regex::new(r"loop\z").unwrap();
-    let function_regex = Regex::new(r"function\z").unwrap();
-    let mod_regex = Regex::new(r"mod\z").unwrap();
-
-    let mut text_itr = program_string.chars();
+    let mut text_itr = Cursor::new(program_string);
     let mut current_char;
     current_char = text_itr.next();
     loop {
-        let mut token;
+        let token;
 
-        if current_char == None {
+        if current_char.is_none() {
             break;
         }
-        //println!("current char in match: {}", current_char.as_ref().unwrap());
+        let start = text_itr.location();
         match current_char.unwrap() {
-            'a'..='z' => {
-                let token_tuple = generate_keyword_regex_token(
-                    &mut text_itr,
-                    &[
-                        (&for_regex, TokenType::For),
-                        (&each_regex, TokenType::Each),
-                        (&else_regex, TokenType::Else),
-                        (&var_regx, TokenType::Assign),
-                        (&is_regex, TokenType::Is),
-                        (&if_regex, TokenType::If),
-                        (&in_regex, TokenType::In),
-                        (&and_regex, TokenType::And),
-                        (&or_regex, TokenType::Or),
-                        (&not_eq_regex, TokenType::NotEqual),
-                        (&not_regex, TokenType::Not),
-                        (&function_regex, TokenType::Function),
-                        (&loop_regex, TokenType::Loop),
-                        (&while_regex, TokenType::While),
-                        (&mod_regex, TokenType::Mod),
-                    ],
-                    &mut current_char.unwrap(),
-                );
-                token = token_tuple.0;
-                current_char = Some(token_tuple.1);
+            'r' if text_itr.at_raw_string() => {
+                token = generate_string_token(TokenType::Str, lex_raw_string(&mut text_itr)?);
+                current_char = text_itr.next();
+            }
+            c if c == '_' || c.is_xid_start() => {
+                while text_itr.peek().is_some_and(|c| c.is_xid_continue()) {
+                    text_itr.next();
+                }
+                let name = text_itr.slice_from(start.offset);
+                token = match KEYWORDS.get(name) {
+                    Some(TokenType::Literal) => generate_literal_token(name),
+                    Some(tok_type) => generate_simple_token(*tok_type),
+                    None => generate_identifier_token(name),
+                };
+                current_char = text_itr.next();
             }
 
+            '=' if text_itr.peek() == Some('=') => {
+                text_itr.next();
+                token = generate_simple_token(TokenType::DoubleEquals);
+                current_char = text_itr.next();
+            }
             '=' => {
                 token = generate_simple_token(TokenType::Equals);
                 current_char = text_itr.next();
@@ -74,48 +226,87 @@ pub fn tokenize(program_string: &mut String) -> Vec<Token> {
                 token = generate_simple_token(TokenType::Minus);
                 current_char = text_itr.next();
             }
+            '*' if text_itr.peek() == Some('*') => {
+                text_itr.next();
+                token = generate_simple_token(TokenType::Pow);
+                current_char = text_itr.next();
+            }
             '*' => {
                 token = generate_simple_token(TokenType::Mult);
                 current_char = text_itr.next();
             }
+            '/' if text_itr.peek() == Some('/') => {
+                text_itr.next();
+                let doc = text_itr.peek() == Some('/');
+                let text = lex_line_comment(&mut text_itr);
+                // `////` and longer are ordinary comments.
+                if doc && !text.starts_with("//") {
+                    trivia.push(Trivia {
+                        text: &text[1..],
+                        span: text_itr.span_from(start),
+                    });
+                }
+                token = None;
+                current_char = text_itr.next();
+            }
+            '/' if text_itr.peek() == Some('*') => {
+                skip_block_comment(&mut text_itr)?;
+                token = None;
+                current_char = text_itr.next();
+            }
+            '#' => {
+                lex_line_comment(&mut text_itr);
+                token = None;
+                current_char = text_itr.next();
+            }
             '/' => {
                 token = generate_simple_token(TokenType::Div);
                 current_char = text_itr.next();
             }
+            '>' if text_itr.peek() == Some('=') => {
+                text_itr.next();
+                token = generate_simple_token(TokenType::Gte);
+                current_char = text_itr.next();
+            }
             '>' => {
-                let token_tuple = generate_operator_regex_token(
-                    &mut text_itr,
-                    &gte_regex,
-                    &mut current_char.unwrap(),
-                    TokenType::Gte,
-                );
-                token = token_tuple.0;
-                current_char = Some(token_tuple.1);
-                if token.is_none() {
-                    token = generate_simple_token(TokenType::GreaterThan);
-                    current_char = text_itr.next();
-                }
+                token = generate_simple_token(TokenType::GreaterThan);
+                current_char = text_itr.next();
+            }
+            '<' if text_itr.peek() == Some('=') => {
+                text_itr.next();
+                token = generate_simple_token(TokenType::Lte);
+                current_char = text_itr.next();
             }
             '<' => {
-                let token_tuple = generate_operator_regex_token(
-                    &mut text_itr,
-                    &lte_regex,
-                    &mut current_char.unwrap(),
-                    TokenType::Lte,
-                );
-                token = token_tuple.0;
-                current_char = Some(token_tuple.1);
-                if token.is_none() {
-                    token = generate_simple_token(TokenType::LessThan);
-                    current_char = text_itr.next();
-                }
+                token = generate_simple_token(TokenType::LessThan);
+                current_char = text_itr.next();
             }
             '{' => {
+                if let Some(template) = templates.last_mut() {
+                    template.depth += 1;
+                }
                 token = generate_simple_token(TokenType::OpenBrace);
                 current_char = text_itr.next();
             }
 
+            '}' if templates.last().is_some_and(|t| t.depth == 0) => {
+                let mut template = templates.pop().unwrap();
+                let (text, end) = lex_string(&mut text_itr, template.quote, template.open)?;
+                token = match end {
+                    Segment::Closed => generate_string_token(TokenType::TemplateTail, text),
+                    Segment::Interpolated => {
+                        template.interpolation = interpolation_start(&text_itr);
+                        templates.push(template);
+                        generate_string_token(TokenType::TemplateMiddle, text)
+                    }
+                };
+                current_char = text_itr.next();
+            }
+
             '}' => {
+                if let Some(template) = templates.last_mut() {
+                    template.depth -= 1;
+                }
                 token = generate_simple_token(TokenType::CloseBrace);
                 current_char = text_itr.next();
             }
@@ -151,300 +342,904 @@ pub fn tokenize(program_string: &mut String) -> Vec<Token> {
                 token = generate_simple_token(TokenType::Colon);
                 current_char = text_itr.next();
             }
-            '"' => {
-                token = generate_simple_token(TokenType::Quote);
+            // A '.' followed by a digit starts a number instead.
+            '.' if !text_itr.peek().is_some_and(|c| c.is_ascii_digit()) => {
+                token = generate_simple_token(TokenType::Period);
                 current_char = text_itr.next();
             }
-            ' ' => {
+            quote @ ('"' | '\'') => {
+                let open = text_itr.location();
+                let (text, end) = lex_string(&mut text_itr, quote, open)?;
+                token = match end {
+                    Segment::Closed => generate_string_token(TokenType::Str, text),
+                    Segment::Interpolated => {
+                        templates.push(Template {
+                            quote,
+                            open,
+                            interpolation: interpolation_start(&text_itr),
+                            depth: 0,
+                        });
+                        generate_string_token(TokenType::TemplateHead, text)
+                    }
+                };
+                current_char = text_itr.next();
+            }
+            ' ' | '\t' | '\r' => {
                 token = None;
                 current_char = text_itr.next();
             }
-            '0'..='9' => {
-                let mut acc = String::from("");
-                while current_char != None
-                    && (current_char.unwrap().is_numeric() || current_char.unwrap() == '.')
-                {
-                    acc.push(current_char.unwrap().clone());
-                    current_char = text_itr.next();
-                }
-                token = generate_literal_token(acc);
-            }
-            _ => {
-                let mut acc = String::from("");
-
-                loop {
-                    println!("acc: <{}>", acc);
-                    println!("curr: <{}>", current_char.as_ref().unwrap_or_else(|| &' '));
-                    if current_char == None
-                        || current_char.unwrap().is_ascii_punctuation()
-                        || current_char.unwrap().is_whitespace()
-                        || current_char.unwrap().is_alphanumeric() != true
-                    {
-                        break;
-                    }
-                    acc.push(current_char.unwrap().clone());
-                    current_char = text_itr.next();
-                }
-                token = generate_literal_token(acc);
+            c @ ('0'..='9' | '.')
+                if c != '.' || text_itr.peek().is_some_and(|c| c.is_ascii_digit()) =>
+            {
+                lex_number(&mut text_itr, c)?;
+                token = generate_literal_token(text_itr.slice_from(start.offset));
+                current_char = text_itr.next();
+            }
+            c => {
+                return Err(LetscriptError::lex(
+                    span_at(start, c.len_utf8()),
+                    format!("unexpected character '{}'", c),
+                ));
             }
         }
-        match token {
-            Some(t) => tokens.push(t),
-            None => {}
+        if let Some(mut t) = token {
+            t.span = Span {
+                line: start.line,
+                column: start.column,
+                start: start.offset,
+                end: text_itr.location().offset,
+            };
+            if t.tok_type != TokenType::NewLine {
+                t.trivia = std::mem::take(&mut trivia);
+            }
+            tokens.push(t)
         }
+    }
+    if let Some(template) = templates.last() {
+        return Err(LetscriptError::lex(
+            span_at(template.interpolation, 2),
+            UNTERMINATED_INTERPOLATION,
+        ));
+    }
+    Ok(tokens)
+}
 
-        // println!("next char: <{}>", current_char.as_ref().unwrap());
+/// Span of the `len` bytes of text found at `at`.
+fn span_at(at: Position, len: usize) -> Span {
+    Span {
+        line: at.line,
+        column: at.column,
+        start: at.offset,
+        end: at.offset + len,
     }
-    tokens
 }
 
-fn generate_simple_token(tok_type: TokenType) -> Option<Token> {
+fn generate_simple_token<'a>(tok_type: TokenType) -> Option<Token<'a>> {
     Some(Token {
-        tok_type: tok_type,
+        tok_type,
         tok_value: None,
+        span: Span::default(),
+        trivia: Vec::new(),
     })
 }
 
-fn generate_regex_token(
-    stream: &mut Chars,
-    regex: &Regex,
-    current: &mut char,
-    tok_type: TokenType,
-) -> (Option<Token>, char) {
-    let mut acc = String::from("");
+/// Reads the rest of a number whose first character, `first`, is the
+/// current one, leaving the cursor on its last character. Accepts `0x`,
+/// `0o` and `0b` integers, decimals with an optional fraction and
+/// exponent, and `_` between digits. Only the syntax is checked here; the
+/// parser works out the value.
+fn lex_number(stream: &mut Cursor, first: char) -> Result<()> {
+    let start = stream.location();
+    let radix = match (first, stream.peek()) {
+        ('0', Some('x' | 'X')) => Some((16, "hexadecimal")),
+        ('0', Some('o' | 'O')) => Some((8, "octal")),
+        ('0', Some('b' | 'B')) => Some((2, "binary")),
+        _ => None,
+    };
+    if let Some((radix, name)) = radix {
+        stream.next();
+        let mut digits = 0;
+        let mut previous = None;
+        while let Some(c) = stream.peek().filter(|c| c.is_xid_continue()) {
+            stream.next();
+            if c.is_digit(radix) {
+                digits += 1;
+            } else if c != '_' {
+                return Err(LetscriptError::lex(
+                    span_at(stream.location(), c.len_utf8()),
+                    format!("invalid digit '{}' in {} literal", c, name),
+                ));
+            } else if !previous.is_some_and(|p: char| p.is_digit(radix))
+                || !stream.peek().is_some_and(|n| n.is_digit(radix))
+            {
+                return Err(misplaced_separator(stream));
+            }
+            previous = Some(c);
+        }
+        if digits == 0 {
+            return Err(LetscriptError::lex(
+                stream.span_from(start),
+                format!("expected {} digits", name),
+            ));
+        }
+        return Ok(());
+    }
 
-    while *current != ' ' && current.is_alphanumeric() {
-        acc.push(current.clone());
-        println!("loop char regex = {}", current.clone());
-        *current = stream.next().unwrap();
+    // Each use starts at a digit, so a `_` eaten here always follows one.
+    let eat_digits = |stream: &mut Cursor| {
+        while stream.peek().is_some_and(|c| c.is_ascii_digit())
+            || stream.peek() == Some('_')
+                && stream.peek_second().is_some_and(|c| c.is_ascii_digit())
+        {
+            stream.next();
+        }
+    };
+    eat_digits(stream);
+    if first != '.'
+        && stream.peek() == Some('.')
+        && stream.peek_second().is_some_and(|c| c.is_ascii_digit())
+    {
+        stream.next();
+        eat_digits(stream);
     }
-    println!("regex text: {}", acc.clone().as_str());
-
-    if regex.is_match(acc.as_str()) {
-        return (
-            Some(Token {
-                tok_type: tok_type,
-                tok_value: None,
-            }),
-            *current,
-        );
+    if matches!(stream.peek(), Some('e' | 'E')) {
+        stream.next();
+        if matches!(stream.peek(), Some('+' | '-')) {
+            stream.next();
+        }
+        if !stream.peek().is_some_and(|c| c.is_ascii_digit()) {
+            return Err(LetscriptError::lex(
+                stream.span_from(start),
+                "expected digits in the exponent",
+            ));
+        }
+        eat_digits(stream);
     }
+    if let Some(c) = stream.peek().filter(|c| c.is_xid_continue()) {
+        stream.next();
+        if c == '_' {
+            return Err(misplaced_separator(stream));
+        }
+        return Err(LetscriptError::lex(
+            span_at(stream.location(), c.len_utf8()),
+            format!("invalid character '{}' in number", c),
+        ));
+    }
+    Ok(())
+}
 
-    return (generate_literal_token(acc), *current);
+/// The error for a `_` in a number, the current character, that does not
+/// sit between two digits.
+fn misplaced_separator(stream: &Cursor) -> LetscriptError {
+    LetscriptError::lex(
+        span_at(stream.location(), 1),
+        "'_' in a number must be between two digits",
+    )
 }
 
-fn generate_keyword_regex_token(
-    stream: &mut Chars,
-    types: &[(&Regex, TokenType)],
-    current: &mut char,
-) -> (Option<Token>, char) {
-    let mut acc = String::from("");
+/// Reads the rest of the line after the current character, stopping
+/// before the newline.
+fn lex_line_comment<'a>(stream: &mut Cursor<'a>) -> &'a str {
+    let start = stream.location().offset + 1;
+    while stream.peek().is_some_and(|c| c != '\n') {
+        stream.next();
+    }
+    &stream.source[start..stream.upcoming.offset]
+}
 
-    while *current != ' ' && (current.is_alphanumeric() || *current == '_') {
-        acc.push(current.clone());
-        println!("loop char regex = {}", current.clone());
-        *current = stream.next().unwrap_or_else(|| ' ');
+/// Skips a `/* */` comment whose `/` is the current character, leaving
+/// the cursor on the closing `/`. Comments nest, so each `/*` inside needs
+/// its own `*/`.
+fn skip_block_comment(stream: &mut Cursor) -> Result<()> {
+    let open = stream.location();
+    stream.next();
+    let mut depth = 1;
+    while depth > 0 {
+        match stream.next() {
+            Some('/') if stream.peek() == Some('*') => {
+                stream.next();
+                depth += 1;
+            }
+            Some('*') if stream.peek() == Some('/') => {
+                stream.next();
+                depth -= 1;
+            }
+            Some(_) => {}
+            None => {
+                return Err(LetscriptError::lex(
+                    span_at(open, 2),
+                    UNTERMINATED_BLOCK_COMMENT,
+                ))
+            }
+        }
     }
-    println!("regex text: {}", acc.clone().as_str());
-
-    for (regex, tok_type) in types {
-        if regex.is_match(acc.as_str()) {
-            return (
-                Some(Token {
-                    tok_type: *tok_type,
-                    tok_value: None,
-                }),
-                *current,
-            );
+    Ok(())
+}
+
+/// Reads the text of a string delimited by `quote`, decoding escape
+/// sequences, from the character after the current one. Stops on the
+/// closing quote or on a `${`; `open` is where the string started. The
+/// text is only copied once an escape sequence needs decoding.
+fn lex_string<'a>(
+    stream: &mut Cursor<'a>,
+    quote: char,
+    open: Position,
+) -> Result<(Cow<'a, str>, Segment)> {
+    let start = stream.location().offset + 1;
+    let mut decoded: Option<String> = None;
+    loop {
+        let end = stream.upcoming.offset;
+        let text = |decoded: Option<String>| match decoded {
+            Some(text) => Cow::Owned(text),
+            None => Cow::Borrowed(&stream.source[start..end]),
+        };
+        match stream.next() {
+            Some(c) if c == quote => return Ok((text(decoded), Segment::Closed)),
+            Some('$') if stream.peek() == Some('{') => {
+                stream.next();
+                return Ok((text(decoded), Segment::Interpolated));
+            }
+            Some('\\') => {
+                let escaped = lex_escape(stream)?;
+                decoded
+                    .get_or_insert_with(|| stream.source[start..end].to_string())
+                    .push(escaped);
+            }
+            Some('\n') | None => {
+                return Err(LetscriptError::lex(span_at(open, 1), "unterminated string"))
+            }
+            Some(c) => {
+                if let Some(decoded) = &mut decoded {
+                    decoded.push(c);
+                }
+            }
         }
     }
+}
 
-    return (generate_literal_token(acc), *current);
+/// Decodes the escape sequence following a backslash, which is the
+/// current character.
+fn lex_escape(stream: &mut Cursor) -> Result<char> {
+    let backslash = stream.location();
+    let escaped = match stream.next() {
+        Some('n') => '\n',
+        Some('t') => '\t',
+        Some('r') => '\r',
+        Some('0') => '\0',
+        Some(c @ ('"' | '\'' | '\\' | '$')) => c,
+        Some('u') => return lex_unicode_escape(stream, backslash),
+        Some('\n') | None => {
+            return Err(LetscriptError::lex(
+                stream.span_from(backslash),
+                "unterminated string",
+            ))
+        }
+        Some(c) => {
+            return Err(LetscriptError::lex(
+                stream.span_from(backslash),
+                format!("unknown escape sequence '\\{}'", c),
+            ))
+        }
+    };
+    Ok(escaped)
 }
 
-fn generate_operator_regex_token(
-    stream: &mut Chars,
-    regex: &Regex,
-    current: &mut char,
-    tok_type: TokenType,
-) -> (Option<Token>, char) {
-    let mut acc = String::from("");
-    while *current != ' ' && current.is_ascii_punctuation() {
-        acc.push(current.clone());
-        //println!("loop char = {}", current.clone());
-        *current = stream.next().unwrap();
+/// Decodes the `{XXXX}` part of a `\u{XXXX}` escape: one to six hex digits
+/// naming a Unicode scalar value.
+fn lex_unicode_escape(stream: &mut Cursor, backslash: Position) -> Result<char> {
+    let invalid = |stream: &Cursor| {
+        LetscriptError::lex(
+            stream.span_from(backslash),
+            "invalid unicode escape, expected '\\u{' followed by 1 to 6 hex digits and '}'",
+        )
+    };
+    if stream.next() != Some('{') {
+        return Err(invalid(stream));
     }
-    if regex.is_match(acc.as_str()) {
-        return (
-            Some(Token {
-                tok_type: tok_type,
-                tok_value: None,
-            }),
-            *current,
-        );
+    let mut digits = String::new();
+    loop {
+        match stream.next() {
+            Some('}') => break,
+            Some(c) if c.is_ascii_hexdigit() && digits.len() < 6 => digits.push(c),
+            _ => return Err(invalid(stream)),
+        }
     }
-    if acc == "<" {
-        return (generate_simple_token(TokenType::LessThan), *current);
-    } else if acc == ">" {
-        return (generate_simple_token(TokenType::GreaterThan), *current);
+    let code = u32::from_str_radix(&digits, 16).map_err(|_| invalid(stream))?;
+    char::from_u32(code).ok_or_else(|| {
+        LetscriptError::lex(
+            stream.span_from(backslash),
+            format!("'{:X}' is not a valid unicode character", code),
+        )
+    })
+}
+
+/// Reads a raw string `r"..."`, where the current character is the `r`.
+/// The contents are kept verbatim and may span lines; the quotes may be
+/// wrapped in matching `#` characters so the string can contain `"`.
+fn lex_raw_string<'a>(stream: &mut Cursor<'a>) -> Result<&'a str> {
+    let open = stream.location();
+    let mut hashes = 0;
+    while stream.next() == Some('#') {
+        hashes += 1;
+    }
+    let start = stream.upcoming.offset;
+    loop {
+        let end = stream.upcoming.offset;
+        match stream.next() {
+            Some('"') if stream.eat_hashes(hashes) => return Ok(&stream.source[start..end]),
+            Some(_) => {}
+            None => {
+                return Err(LetscriptError::lex(
+                    span_at(open, 1),
+                    UNTERMINATED_RAW_STRING,
+                ))
+            }
+        }
     }
-    return (generate_literal_token(acc), *current);
 }
 
-fn generate_literal_token(from: String) -> Option<Token> {
-    // let int_regex = Regex::new(r"\d+\z").unwrap();
-    // let float_regex = Regex::new(r"\d+\.\d+\z").unwrap();
-    return Some(Token {
-        tok_type: TokenType::Literal,
+/// Position of the `$` of the `${` that was just read.
+fn interpolation_start(stream: &Cursor) -> Position {
+    let brace = stream.location();
+    Position {
+        offset: brace.offset - 1,
+        line: brace.line,
+        column: brace.column - 1,
+    }
+}
+
+fn generate_string_token<'a>(
+    tok_type: TokenType,
+    contents: impl Into<Cow<'a, str>>,
+) -> Option<Token<'a>> {
+    Some(Token {
+        tok_type,
         tok_value: Some(TokenValue {
-            s_val: Some(from.clone()),
+            s_val: Some(contents.into()),
         }),
-    });
+        span: Span::default(),
+        trivia: Vec::new(),
+    })
 }
 
-#[test]
-pub fn test_regex() {
-    let input_string = String::from("var x = 12");
-    let mut itr = input_string.chars();
-    let mut current = itr.next().unwrap();
-    let token = generate_regex_token(
-        &mut itr,
-        &mut Regex::new(r"var\z").unwrap(),
-        &mut current,
-        TokenType::Assign,
-    )
-    .0;
-    assert_eq!(token.is_some(), true);
-    assert_eq!(matches!(token.unwrap().tok_type, TokenType::Assign), true);
+fn generate_identifier_token(name: &str) -> Option<Token<'_>> {
+    Some(Token {
+        tok_type: TokenType::Identifier,
+        tok_value: Some(TokenValue {
+            s_val: Some(Cow::Borrowed(name)),
+        }),
+        span: Span::default(),
+        trivia: Vec::new(),
+    })
+}
+
+fn generate_literal_token<'a>(from: impl Into<Cow<'a, str>>) -> Option<Token<'a>> {
+    Some(Token {
+        tok_type: TokenType::Literal,
+        tok_value: Some(TokenValue {
+            s_val: Some(from.into()),
+        }),
+        span: Span::default(),
+        trivia: Vec::new(),
+    })
 }
 
 #[test]
 pub fn test_simple() {
     let token = generate_simple_token(TokenType::Equals);
-    assert_eq!(token.is_some(), true);
-    assert_eq!(matches!(token.unwrap().tok_type, TokenType::Equals), true);
+    assert!(token.is_some());
+    assert!(matches!(token.unwrap().tok_type, TokenType::Equals));
 }
 
 #[test]
 pub fn test_literal() {
     let input_string = String::from("hello world");
     let mut token = generate_literal_token(input_string);
-    assert_eq!(token.is_some(), true);
-    assert_eq!(matches!(token.unwrap().tok_type, TokenType::Literal), true);
+    assert!(token.is_some());
+    assert!(matches!(token.unwrap().tok_type, TokenType::Literal));
     let input_int = String::from("1");
     token = generate_literal_token(input_int);
-    assert_eq!(token.is_some(), true);
-    assert_eq!(matches!(token.unwrap().tok_type, TokenType::Literal), true);
+    assert!(token.is_some());
+    assert!(matches!(token.unwrap().tok_type, TokenType::Literal));
     let input_float = String::from("0.2394");
     token = generate_literal_token(input_float);
-    assert_eq!(token.is_some(), true);
-    assert_eq!(matches!(token.unwrap().tok_type, TokenType::Literal), true);
+    assert!(token.is_some());
+    assert!(matches!(token.unwrap().tok_type, TokenType::Literal));
 }
 
 #[test]
 pub fn test_tokenizer_assignment() {
-    let mut input = String::from(" var x= 12 \n");
-    let tokens = tokenize(&mut input);
+    let input = String::from(" var x= 12 \n");
+    let tokens = tokenize(&input).unwrap();
     tokens.iter().for_each(|t| println!("{:?}", t.tok_type));
-    assert_eq!(matches!(tokens[0].tok_type, TokenType::Assign), true);
-    assert_eq!(matches!(tokens[1].tok_type, TokenType::Literal), true);
-    assert_eq!(matches!(tokens[2].tok_type, TokenType::Equals), true);
-    assert_eq!(matches!(tokens[3].tok_type, TokenType::Literal), true);
-    assert_eq!(matches!(tokens[4].tok_type, TokenType::NewLine), true);
+    assert!(matches!(tokens[0].tok_type, TokenType::Assign));
+    assert!(matches!(tokens[1].tok_type, TokenType::Identifier));
+    assert!(matches!(tokens[2].tok_type, TokenType::Equals));
+    assert!(matches!(tokens[3].tok_type, TokenType::Literal));
+    assert!(matches!(tokens[4].tok_type, TokenType::NewLine));
     assert_eq!(tokens.len(), 5);
 }
 
 #[test]
 pub fn test_tokenizer_not_eq() {
-    let mut input = String::from("  is_not_running = false \n");
-    let tokens = tokenize(&mut input);
+    let input = String::from("  is_not_running = false \n");
+    let tokens = tokenize(&input).unwrap();
     tokens.iter().for_each(|t| println!("{:?}", t.tok_type));
-    assert_eq!(matches!(tokens[0].tok_type, TokenType::Literal), true);
-    assert_eq!(matches!(tokens[1].tok_type, TokenType::Equals), true);
-    assert_eq!(matches!(tokens[2].tok_type, TokenType::Literal), true);
+    assert!(matches!(tokens[0].tok_type, TokenType::Identifier));
+    assert!(matches!(tokens[1].tok_type, TokenType::Equals));
+    assert!(matches!(tokens[2].tok_type, TokenType::Literal));
 
     assert_eq!(tokens.len(), 4);
 }
 
 #[test]
 pub fn test_tokenizer_combinator() {
-    let mut input = String::from("1+ 4 -3.5 *97 / 4 \n");
-    let tokens = tokenize(&mut input);
+    let input = String::from("1+ 4 -3.5 *97 / 4 \n");
+    let tokens = tokenize(&input).unwrap();
     tokens.iter().for_each(|t| println!("{:?}", t.tok_type));
-    assert_eq!(matches!(tokens[0].tok_type, TokenType::Literal), true);
-    assert_eq!(matches!(tokens[1].tok_type, TokenType::Plus), true);
-    assert_eq!(matches!(tokens[2].tok_type, TokenType::Literal), true);
-    assert_eq!(matches!(tokens[3].tok_type, TokenType::Minus), true);
-    assert_eq!(matches!(tokens[4].tok_type, TokenType::Literal), true);
+    assert!(matches!(tokens[0].tok_type, TokenType::Literal));
+    assert!(matches!(tokens[1].tok_type, TokenType::Plus));
+    assert!(matches!(tokens[2].tok_type, TokenType::Literal));
+    assert!(matches!(tokens[3].tok_type, TokenType::Minus));
+    assert!(matches!(tokens[4].tok_type, TokenType::Literal));
     assert_eq!(tokens.len(), 10);
 }
 
 #[test]
 pub fn test_tokenizer_boolean_expression() {
-    let mut input = String::from("3 <5 or 7 = 2 and 5>=4 or 0.77<= y\n");
-    let tokens = tokenize(&mut input);
+    let input = String::from("3 <5 or 7 = 2 and 5>=4 or 0.77<= y\n");
+    let tokens = tokenize(&input).unwrap();
     tokens.iter().for_each(|t| println!("{:?}", t.tok_type));
 
-    assert_eq!(matches!(tokens[0].tok_type, TokenType::Literal), true);
-    assert_eq!(matches!(tokens[3].tok_type, TokenType::Or), true);
-    assert_eq!(matches!(tokens[5].tok_type, TokenType::Equals), true);
-    assert_eq!(matches!(tokens[7].tok_type, TokenType::And), true);
-    assert_eq!(matches!(tokens[9].tok_type, TokenType::Gte), true);
+    assert!(matches!(tokens[0].tok_type, TokenType::Literal));
+    assert!(matches!(tokens[3].tok_type, TokenType::Or));
+    assert!(matches!(tokens[5].tok_type, TokenType::Equals));
+    assert!(matches!(tokens[7].tok_type, TokenType::And));
+    assert!(matches!(tokens[9].tok_type, TokenType::Gte));
     assert_eq!(tokens.len(), 16);
 }
 
+#[test]
+pub fn test_tokenizer_power() {
+    let types: Vec<TokenType> = tokenize("2 ** 3 * 4 == x")
+        .unwrap()
+        .iter()
+        .map(|t| t.tok_type)
+        .collect();
+    assert_eq!(
+        types,
+        [
+            TokenType::Literal,
+            TokenType::Pow,
+            TokenType::Literal,
+            TokenType::Mult,
+            TokenType::Literal,
+            TokenType::DoubleEquals,
+            TokenType::Identifier,
+        ]
+    );
+}
+
+#[test]
+pub fn test_tokenizer_member_access() {
+    let types: Vec<TokenType> = tokenize("user.name .5 rows[0].id")
+        .unwrap()
+        .iter()
+        .map(|t| t.tok_type)
+        .collect();
+    assert_eq!(
+        types,
+        [
+            TokenType::Identifier,
+            TokenType::Period,
+            TokenType::Identifier,
+            TokenType::Literal,
+            TokenType::Identifier,
+            TokenType::OpenBracket,
+            TokenType::Literal,
+            TokenType::CloseBracket,
+            TokenType::Period,
+            TokenType::Identifier,
+        ]
+    );
+}
+
 #[test]
 pub fn test_tokenizer_bracket() {
-    //TODO : DEBUG INF LOOP IN STRING
-    let mut input = String::from("[4, 3] {\"hello\": \"worlds\"} ((x + 4)- 19)\n");
-    let tokens = tokenize(&mut input);
+    let input = String::from("[4, 3] {\"hello\": \"worlds\"} ((x + 4)- 19)\n");
+    let tokens = tokenize(&input).unwrap();
     tokens.iter().for_each(|t| println!("{:?}", t.tok_type));
-    assert_eq!(matches!(tokens[0].tok_type, TokenType::OpenBracket), true);
-    assert_eq!(matches!(tokens[4].tok_type, TokenType::CloseBracket), true);
-    assert_eq!(matches!(tokens[5].tok_type, TokenType::OpenBrace), true);
-    assert_eq!(matches!(tokens[13].tok_type, TokenType::CloseBrace), true);
-    assert_eq!(matches!(tokens[14].tok_type, TokenType::OpenParen), true);
-    assert_eq!(matches!(tokens[15].tok_type, TokenType::OpenParen), true);
-    assert_eq!(matches!(tokens[19].tok_type, TokenType::CloseParen), true);
-    assert_eq!(matches!(tokens[22].tok_type, TokenType::CloseParen), true);
-    assert_eq!(tokens.len(), 24);
+    assert!(matches!(tokens[0].tok_type, TokenType::OpenBracket));
+    assert!(matches!(tokens[4].tok_type, TokenType::CloseBracket));
+    assert!(matches!(tokens[5].tok_type, TokenType::OpenBrace));
+    assert!(matches!(tokens[6].tok_type, TokenType::Str));
+    assert!(matches!(tokens[9].tok_type, TokenType::CloseBrace));
+    assert!(matches!(tokens[10].tok_type, TokenType::OpenParen));
+    assert!(matches!(tokens[11].tok_type, TokenType::OpenParen));
+    assert!(matches!(tokens[15].tok_type, TokenType::CloseParen));
+    assert!(matches!(tokens[18].tok_type, TokenType::CloseParen));
+    assert_eq!(tokens.len(), 20);
 }
 
 #[test]
 pub fn test_tokenizer_conditionals() {
-    let mut input = String::from("if(x in y){}\nelse if(x not = 3){}\nelse{}\n");
-    let tokens = tokenize(&mut input);
+    let input = String::from("if(x in y){}\nelse if(x not = 3){}\nelse{}\n");
+    let tokens = tokenize(&input).unwrap();
     tokens.iter().for_each(|t| println!("{:?}", t.tok_type));
-    assert_eq!(matches!(tokens[0].tok_type, TokenType::If), true);
-    assert_eq!(matches!(tokens[9].tok_type, TokenType::Else), true);
-    assert_eq!(matches!(tokens[10].tok_type, TokenType::If), true);
-    assert_eq!(matches!(tokens[20].tok_type, TokenType::Else), true);
-    assert_eq!(matches!(tokens[23].tok_type, TokenType::NewLine), true);
+    assert!(matches!(tokens[0].tok_type, TokenType::If));
+    assert!(matches!(tokens[9].tok_type, TokenType::Else));
+    assert!(matches!(tokens[10].tok_type, TokenType::If));
+    assert!(matches!(tokens[20].tok_type, TokenType::Else));
+    assert!(matches!(tokens[23].tok_type, TokenType::NewLine));
     assert_eq!(tokens.len(), 24);
 }
 
 #[test]
 pub fn test_tokenizer_loops() {
-    let mut input = String::from("for each(var x in y) {} loop while (x not = 3)\n");
-    let tokens = tokenize(&mut input);
+    let input = String::from("for each(var x in y) {} loop while (x not = 3)\n");
+    let tokens = tokenize(&input).unwrap();
     tokens.iter().for_each(|t| println!("{:?}", t.tok_type));
-    assert_eq!(matches!(tokens[0].tok_type, TokenType::For), true);
-    assert_eq!(matches!(tokens[1].tok_type, TokenType::Each), true);
-    assert_eq!(matches!(tokens[2].tok_type, TokenType::OpenParen), true);
-    assert_eq!(matches!(tokens[7].tok_type, TokenType::CloseParen), true);
-    assert_eq!(matches!(tokens[9].tok_type, TokenType::CloseBrace), true);
+    assert!(matches!(tokens[0].tok_type, TokenType::For));
+    assert!(matches!(tokens[1].tok_type, TokenType::Each));
+    assert!(matches!(tokens[2].tok_type, TokenType::OpenParen));
+    assert!(matches!(tokens[7].tok_type, TokenType::CloseParen));
+    assert!(matches!(tokens[9].tok_type, TokenType::CloseBrace));
     assert_eq!(tokens.len(), 19);
 }
 
 #[test]
 pub fn test_tokenizer_functions() {
-    let mut input = String::from("function(x, y, zee )\n{loop while (x not = 3){} }\n");
-    let tokens = tokenize(&mut input);
+    let input = String::from("function(x, y, zee )\n{loop while (x not = 3){} }\n");
+    let tokens = tokenize(&input).unwrap();
     tokens.iter().for_each(|t| println!("{:?}", t.tok_type));
-    assert_eq!(matches!(tokens[0].tok_type, TokenType::Function), true);
-    assert_eq!(matches!(tokens[1].tok_type, TokenType::OpenParen), true);
-    assert_eq!(matches!(tokens[5].tok_type, TokenType::Comma), true);
-    assert_eq!(matches!(tokens[6].tok_type, TokenType::Literal), true);
-    assert_eq!(matches!(tokens[10].tok_type, TokenType::Loop), true);
+    assert!(matches!(tokens[0].tok_type, TokenType::Function));
+    assert!(matches!(tokens[1].tok_type, TokenType::OpenParen));
+    assert!(matches!(tokens[5].tok_type, TokenType::Comma));
+    assert!(matches!(tokens[6].tok_type, TokenType::Identifier));
+    assert!(matches!(tokens[10].tok_type, TokenType::Loop));
     assert_eq!(tokens.len(), 22);
 }
+
+#[test]
+pub fn test_tokenizer_spans() {
+    let input = String::from("var x is 12\n  \"é\" >= y\n");
+    let tokens = tokenize(&input).unwrap();
+    let span = |line, column, start, end| Span {
+        line,
+        column,
+        start,
+        end,
+    };
+    assert_eq!(tokens[0].span, span(1, 1, 0, 3));
+    assert_eq!(tokens[3].span, span(1, 10, 9, 11));
+    assert_eq!(tokens[4].span, span(1, 12, 11, 12));
+    assert_eq!(tokens[5].span, span(2, 3, 14, 18));
+    assert_eq!(tokens[6].span, span(2, 7, 19, 21));
+    assert_eq!(tokens[7].span, span(2, 10, 22, 23));
+    assert_eq!(&input[tokens[5].span.start..tokens[5].span.end], "\"é\"");
+}
+
+#[test]
+pub fn test_tokenizer_unexpected_character() {
+    let input = String::from("var x is 1\nvar y is x ; 2\n");
+    let err = tokenize(&input).unwrap_err();
+    assert_eq!(
+        err,
+        LetscriptError::lex(
+            Span {
+                line: 2,
+                column: 12,
+                start: 22,
+                end: 23,
+            },
+            "unexpected character ';'",
+        )
+    );
+}
+
+#[test]
+pub fn test_tokenizer_strings() {
+    let strings = |input: &str| -> Vec<String> {
+        tokenize(input)
+            .unwrap()
+            .into_iter()
+            .filter(|t| t.tok_type == TokenType::Str)
+            .map(|t| t.tok_value.unwrap().s_val.unwrap().into_owned())
+            .collect()
+    };
+    assert_eq!(strings("\"hello world\" ''"), ["hello world", ""]);
+    assert_eq!(
+        strings(r#""a\tb\n\"q\" \\ \u{e9}\u{1F600}" 'it\'s "fine"'"#),
+        ["a\tb\n\"q\" \\ é😀", "it's \"fine\""]
+    );
+    assert_eq!(
+        strings("r\"C:\\path\\n\" r#\"say \"hi\"\nthere\"#"),
+        ["C:\\path\\n", "say \"hi\"\nthere"]
+    );
+
+    let tokens = tokenize("var s is r\"a\nb\" + rest\nx").unwrap();
+    assert!(matches!(tokens[1].tok_type, TokenType::Identifier));
+    assert_eq!(tokens[3].span.line, 1);
+    assert_eq!(tokens[4].span.line, 2);
+    assert!(matches!(tokens[5].tok_type, TokenType::Identifier));
+    assert_eq!(tokens[7].span.line, 3);
+}
+
+#[test]
+pub fn test_tokenizer_string_errors() {
+    let message = |input: &str| match tokenize(input).unwrap_err() {
+        LetscriptError::Lex { message, span } => (message, span.column),
+        other => panic!("unexpected error {:?}", other),
+    };
+    assert_eq!(message("x is \"open"), ("unterminated string".into(), 6));
+    assert_eq!(message("'line\nbreak'"), ("unterminated string".into(), 1));
+    assert_eq!(message("r#\"raw\""), ("unterminated raw string".into(), 1));
+    assert_eq!(
+        message("\"bad \\q\""),
+        ("unknown escape sequence '\\q'".into(), 6)
+    );
+    assert_eq!(
+        message("\"\\u{110000}\"").0,
+        "'110000' is not a valid unicode character"
+    );
+    assert!(message("\"\\u{zz}\"")
+        .0
+        .starts_with("invalid unicode escape"));
+    assert!(message("\"\\u41\"").0.starts_with("invalid unicode escape"));
+
+    let unterminated = |input: &str| is_unterminated(&tokenize(input).unwrap_err());
+    assert!(unterminated("r\"first line\n"));
+    assert!(unterminated("/* first line\n"));
+    assert!(unterminated("\"a ${ [1,\n"));
+    assert!(!unterminated("x is \"open"));
+    assert!(!unterminated("0b2"));
+}
+
+#[test]
+pub fn test_tokenizer_interpolation() {
+    let input = "\"a ${x + 1} b ${ {k: \"${y}\"} }\\${z}\"";
+    let tokens = tokenize(input).unwrap();
+    let types: Vec<TokenType> = tokens.iter().map(|t| t.tok_type).collect();
+    assert_eq!(
+        types,
+        [
+            TokenType::TemplateHead,
+            TokenType::Identifier,
+            TokenType::Plus,
+            TokenType::Literal,
+            TokenType::TemplateMiddle,
+            TokenType::OpenBrace,
+            TokenType::Identifier,
+            TokenType::Colon,
+            TokenType::TemplateHead,
+            TokenType::Identifier,
+            TokenType::TemplateTail,
+            TokenType::CloseBrace,
+            TokenType::TemplateTail,
+        ]
+    );
+    let text = |i: usize| tokens[i].tok_value.clone().unwrap().s_val.unwrap();
+    assert_eq!(
+        (text(0), text(4), text(12)),
+        ("a ".into(), " b ".into(), "${z}".into())
+    );
+    assert_eq!(&input[tokens[1].span.start..tokens[1].span.end], "x");
+    assert_eq!(tokens[1].span.column, 6);
+    assert_eq!(&input[tokens[4].span.start..tokens[4].span.end], "} b ${");
+
+    let err = tokenize("var s is 'open ${x\n").unwrap_err();
+    assert_eq!(
+        err,
+        LetscriptError::lex(
+            Span {
+                line: 1,
+                column: 16,
+                start: 15,
+                end: 17,
+            },
+            "unterminated interpolation, expected '}'",
+        )
+    );
+}
+
+#[test]
+pub fn test_tokenizer_comments() {
+    let input = "var x is 1 # the answer\n// a whole line\nx = x /* inline */ / 2 // done\n\
+                 /* outer /* nested */ still a comment\n*/ x";
+    let types: Vec<TokenType> = tokenize(input)
+        .unwrap()
+        .iter()
+        .map(|t| t.tok_type)
+        .collect();
+    assert_eq!(
+        types,
+        [
+            TokenType::Assign,
+            TokenType::Identifier,
+            TokenType::Is,
+            TokenType::Literal,
+            TokenType::NewLine,
+            TokenType::NewLine,
+            TokenType::Identifier,
+            TokenType::Equals,
+            TokenType::Identifier,
+            TokenType::Div,
+            TokenType::Literal,
+            TokenType::NewLine,
+            TokenType::Identifier,
+        ]
+    );
+
+    let err = tokenize("x /* open /* nested */\n").unwrap_err();
+    assert_eq!(
+        err,
+        LetscriptError::lex(
+            Span {
+                line: 1,
+                column: 3,
+                start: 2,
+                end: 4,
+            },
+            "unterminated block comment",
+        )
+    );
+}
+
+#[test]
+pub fn test_tokenizer_doc_comments() {
+    let input = "/// Adds one.\n///\n//// not a doc comment\nfunction inc(n) {\n  /// The result.\n  return n + 1\n}\n";
+    let tokens = tokenize(input).unwrap();
+    let function = tokens
+        .iter()
+        .find(|t| t.tok_type == TokenType::Function)
+        .unwrap();
+    let docs: Vec<&str> = function.trivia.iter().map(|t| t.text).collect();
+    assert_eq!(docs, [" Adds one.", ""]);
+    assert_eq!(
+        &input[function.trivia[0].span.start..function.trivia[0].span.end],
+        "/// Adds one."
+    );
+    assert_eq!(function.trivia[1].span.line, 2);
+
+    let ret = tokens
+        .iter()
+        .find(|t| t.tok_type == TokenType::Return)
+        .unwrap();
+    assert_eq!(ret.trivia[0].text, " The result.");
+    let documented = tokens.iter().filter(|t| !t.trivia.is_empty()).count();
+    assert_eq!(documented, 2);
+}
+
+#[test]
+pub fn test_tokenizer_identifiers() {
+    let input =
+        "Total _tmp item2 名前 café format island spin main notice var in isnt true nullable\n";
+    let tokens = tokenize(input).unwrap();
+    let kinds: Vec<(TokenType, &str)> = tokens
+        .iter()
+        .map(|t| (t.tok_type, &input[t.span.start..t.span.end]))
+        .collect();
+    assert_eq!(
+        kinds,
+        [
+            (TokenType::Identifier, "Total"),
+            (TokenType::Identifier, "_tmp"),
+            (TokenType::Identifier, "item2"),
+            (TokenType::Identifier, "名前"),
+            (TokenType::Identifier, "café"),
+            (TokenType::Identifier, "format"),
+            (TokenType::Identifier, "island"),
+            (TokenType::Identifier, "spin"),
+            (TokenType::Identifier, "main"),
+            (TokenType::Identifier, "notice"),
+            (TokenType::Assign, "var"),
+            (TokenType::In, "in"),
+            (TokenType::Identifier, "isnt"),
+            (TokenType::Literal, "true"),
+            (TokenType::Identifier, "nullable"),
+            (TokenType::NewLine, "\n"),
+        ]
+    );
+    assert!(matches!(
+        tokenize("var x is 1 ☃ 2").unwrap_err(),
+        LetscriptError::Lex { .. }
+    ));
+}
+
+#[test]
+pub fn test_tokenizer_borrows_source_text() {
+    let input = "total>=(limit) and 'plain' <'esc\\n'>-1.5";
+    let tokens = tokenize(input).unwrap();
+    let types: Vec<TokenType> = tokens.iter().map(|t| t.tok_type).collect();
+    assert_eq!(
+        types,
+        [
+            TokenType::Identifier,
+            TokenType::Gte,
+            TokenType::OpenParen,
+            TokenType::Identifier,
+            TokenType::CloseParen,
+            TokenType::And,
+            TokenType::Str,
+            TokenType::LessThan,
+            TokenType::Str,
+            TokenType::GreaterThan,
+            TokenType::Minus,
+            TokenType::Literal,
+        ]
+    );
+    let value = |i: usize| {
+        tokens[i]
+            .tok_value
+            .as_ref()
+            .unwrap()
+            .s_val
+            .as_ref()
+            .unwrap()
+    };
+    assert!(matches!(value(0), Cow::Borrowed("total")));
+    assert!(matches!(value(6), Cow::Borrowed("plain")));
+    assert!(matches!(value(8), Cow::Owned(text) if text == "esc\n"));
+    assert!(matches!(value(11), Cow::Borrowed("1.5")));
+}
+
+#[test]
+pub fn test_tokenizer_numbers() {
+    let input = "0xFF 0b1010 0o17 1_000_000 6.02e23 1E-3 .5 3.25 7 0xFF_FF 1_0.2_5e1_0";
+    let lexemes: Vec<&str> = tokenize(input)
+        .unwrap()
+        .iter()
+        .map(|t| &input[t.span.start..t.span.end])
+        .collect();
+    assert_eq!(
+        lexemes,
+        [
+            "0xFF",
+            "0b1010",
+            "0o17",
+            "1_000_000",
+            "6.02e23",
+            "1E-3",
+            ".5",
+            "3.25",
+            "7",
+            "0xFF_FF",
+            "1_0.2_5e1_0"
+        ]
+    );
+
+    let message = |input: &str| match tokenize(input).unwrap_err() {
+        LetscriptError::Lex { message, span } => (message, span.column),
+        other => panic!("unexpected error {:?}", other),
+    };
+    assert_eq!(
+        message("x is 0b102"),
+        ("invalid digit '2' in binary literal".into(), 10)
+    );
+    assert_eq!(message("0x"), ("expected hexadecimal digits".into(), 1));
+    assert_eq!(message("0o"), ("expected octal digits".into(), 1));
+    assert_eq!(
+        message("1e+"),
+        ("expected digits in the exponent".into(), 1)
+    );
+    assert_eq!(
+        message("12abc"),
+        ("invalid character 'a' in number".into(), 3)
+    );
+    for (input, column) in [
+        ("1_.5", 2),
+        ("1__0", 2),
+        ("1_", 2),
+        ("1_e5", 2),
+        ("1.5_", 4),
+        ("1e5_", 4),
+        ("0x_FF", 3),
+        ("0b1__0", 4),
+        ("0o7_", 4),
+    ] {
+        assert_eq!(
+            message(input),
+            ("'_' in a number must be between two digits".into(), column)
+        );
+    }
+}
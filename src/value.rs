@@ -0,0 +1,265 @@
+use crate::ast::{Block, Frame, Scope};
+use crate::error::Result;
+use crate::token::Span;
+use crate::{STACK_GROWTH, STACK_RED_ZONE};
+
+use num_bigint::BigInt;
+use num_traits::ToPrimitive;
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashSet};
+use std::fmt;
+use std::rc::{Rc, Weak};
+
+/// A runtime value. Scalars and strings are stored unboxed; arrays, objects
+/// and functions are shared, so copies of them refer to the same value.
+#[derive(Clone, Debug)]
+pub enum Value {
+    Int(i64),
+    /// An integer outside the range of `Int`. Both are the script's `int`
+    /// type; arithmetic keeps every integer that fits in an `Int`.
+    BigInt(BigInt),
+    Float(f64),
+    Bool(bool),
+    Null,
+    Undefined,
+    Str(String),
+    Array(Rc<RefCell<Vec<Value>>>),
+    Object(Rc<RefCell<BTreeMap<String, Value>>>),
+    Function(Rc<Function>),
+    NativeFunction(NativeFunction),
+}
+
+/// A function written in a script, together with the frames that were in
+/// scope where it was defined. Anonymous functions have an empty name.
+pub struct Function {
+    pub name: String,
+    pub params: Vec<String>,
+    pub body: Rc<Block>,
+    /// The frames enclosing `home`, outermost first.
+    pub env: Vec<Frame>,
+    pub home: RefCell<Home>,
+}
+
+/// The frame a function was defined in. A function stored in that same
+/// frame holds it weakly, so the two do not keep each other alive once the
+/// frame's block has finished.
+#[derive(Clone, Debug)]
+pub enum Home {
+    Strong(Frame),
+    Weak(Weak<RefCell<Scope>>),
+}
+
+impl Function {
+    /// The frames the function's body runs on top of, outermost first.
+    pub fn frames(&self) -> Vec<Frame> {
+        let mut frames = self.env.clone();
+        match &*self.home.borrow() {
+            Home::Strong(frame) => frames.push(frame.clone()),
+            // A weakly held home frame is alive whenever the function can
+            // be reached: the interpreter holds it strongly again before
+            // the function can outlive it.
+            Home::Weak(frame) => frames.extend(frame.upgrade()),
+        }
+        frames
+    }
+
+    /// Whether `frame` is the frame the function was defined in.
+    pub fn is_home(&self, frame: &Frame) -> bool {
+        match &*self.home.borrow() {
+            Home::Strong(home) => Rc::ptr_eq(home, frame),
+            Home::Weak(home) => std::ptr::eq(home.as_ptr(), Rc::as_ptr(frame)),
+        }
+    }
+
+    /// Switches how the function holds its home frame, `frame`.
+    pub fn hold_home(&self, frame: &Frame, weakly: bool) {
+        *self.home.borrow_mut() = if weakly {
+            Home::Weak(Rc::downgrade(frame))
+        } else {
+            Home::Strong(frame.clone())
+        };
+    }
+
+    /// The name used for the function in messages.
+    pub fn display_name(&self) -> &str {
+        if self.name.is_empty() {
+            "anonymous function"
+        } else {
+            &self.name
+        }
+    }
+}
+
+// The captured frames usually contain the function itself.
+impl fmt::Debug for Function {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Function")
+            .field("name", &self.name)
+            .field("params", &self.params)
+            .finish_non_exhaustive()
+    }
+}
+
+/// A function implemented in Rust. `arity` is `None` for functions taking
+/// any number of arguments.
+#[derive(Clone, Copy, Debug)]
+pub struct NativeFunction {
+    pub name: &'static str,
+    pub arity: Option<usize>,
+    pub call: fn(&[Value], Span) -> Result<Value>,
+}
+
+impl Value {
+    /// A new array holding `items`.
+    pub fn array(items: Vec<Value>) -> Value {
+        Value::Array(Rc::new(RefCell::new(items)))
+    }
+
+    /// A new object holding `fields`.
+    pub fn object(fields: BTreeMap<String, Value>) -> Value {
+        Value::Object(Rc::new(RefCell::new(fields)))
+    }
+
+    /// The value of the integer `x`, as an `Int` whenever it fits.
+    pub fn from_big(x: BigInt) -> Value {
+        match x.to_i64() {
+            Some(x) => Value::Int(x),
+            None => Value::BigInt(x),
+        }
+    }
+
+    /// The name of the value's type as used in error messages.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::Int(_) | Value::BigInt(_) => "int",
+            Value::Float(_) => "float",
+            Value::Bool(_) => "bool",
+            Value::Null => "null",
+            Value::Undefined => "undefined",
+            Value::Str(_) => "string",
+            Value::Array(_) => "array",
+            Value::Object(_) => "object",
+            Value::Function(_) | Value::NativeFunction(_) => "function",
+        }
+    }
+
+    /// Moves the elements or fields of an array or object held only by
+    /// `self` into `pending`.
+    fn take_contents(&mut self, pending: &mut Vec<Value>) {
+        match self {
+            Value::Array(items) => {
+                if let Some(items) = Rc::get_mut(items) {
+                    pending.append(items.get_mut());
+                }
+            }
+            Value::Object(fields) => {
+                if let Some(fields) = Rc::get_mut(fields) {
+                    pending.extend(std::mem::take(fields.get_mut()).into_values());
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Formats the value as it appears inside an array or object, where
+    /// strings are quoted. `open` holds the arrays and objects being
+    /// formatted, so one that contains itself shows as `[...]` or `{...}`.
+    fn fmt_nested(&self, f: &mut fmt::Formatter, open: &mut HashSet<*const ()>) -> fmt::Result {
+        stacker::maybe_grow(STACK_RED_ZONE, STACK_GROWTH, || match self {
+            Value::Str(s) => write!(f, "{:?}", s),
+            other => other.fmt_with(f, open),
+        })
+    }
+
+    fn fmt_with(&self, f: &mut fmt::Formatter, open: &mut HashSet<*const ()>) -> fmt::Result {
+        let address = match self {
+            Value::Array(items) => Rc::as_ptr(items) as *const (),
+            Value::Object(fields) => Rc::as_ptr(fields) as *const (),
+            other => return write!(f, "{}", other),
+        };
+        if !open.insert(address) {
+            return match self {
+                Value::Array(_) => write!(f, "[...]"),
+                _ => write!(f, "{{...}}"),
+            };
+        }
+        match self {
+            Value::Array(items) => {
+                write!(f, "[")?;
+                for (i, item) in items.borrow().iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    item.fmt_nested(f, open)?;
+                }
+                write!(f, "]")?;
+            }
+            Value::Object(fields) => {
+                write!(f, "{{")?;
+                for (i, (key, value)) in fields.borrow().iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}: ", key)?;
+                    value.fmt_nested(f, open)?;
+                }
+                write!(f, "}}")?;
+            }
+            _ => {}
+        }
+        open.remove(&address);
+        Ok(())
+    }
+}
+
+/// Values are equal when they have the same type and contents, compared
+/// deeply. Unlike the script's `=`, an int never equals a float.
+impl PartialEq for Value {
+    fn eq(&self, other: &Value) -> bool {
+        match (self, other) {
+            (Value::Int(l), Value::Int(r)) => l == r,
+            (Value::BigInt(l), Value::BigInt(r)) => l == r,
+            (Value::Float(l), Value::Float(r)) => l == r,
+            (Value::Bool(l), Value::Bool(r)) => l == r,
+            (Value::Null, Value::Null) | (Value::Undefined, Value::Undefined) => true,
+            (Value::Str(l), Value::Str(r)) => l == r,
+            (Value::Array(l), Value::Array(r)) => l == r,
+            (Value::Object(l), Value::Object(r)) => l == r,
+            (Value::Function(l), Value::Function(r)) => Rc::ptr_eq(l, r),
+            (Value::NativeFunction(l), Value::NativeFunction(r)) => l.name == r.name,
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Value::Int(x) => write!(f, "{}", x),
+            Value::BigInt(x) => write!(f, "{}", x),
+            // Debug formatting keeps the decimal point on whole floats.
+            Value::Float(x) => write!(f, "{:?}", x),
+            Value::Bool(x) => write!(f, "{}", x),
+            Value::Null => write!(f, "null"),
+            Value::Undefined => write!(f, "undefined"),
+            Value::Str(s) => write!(f, "{}", s),
+            Value::Array(_) | Value::Object(_) => self.fmt_with(f, &mut HashSet::new()),
+            Value::Function(function) if function.name.is_empty() => write!(f, "<function>"),
+            Value::Function(function) => write!(f, "<function {}>", function.name),
+            Value::NativeFunction(native) => write!(f, "<native function {}>", native.name),
+        }
+    }
+}
+
+/// Arrays and objects can nest deeper than the native stack allows
+/// recursion, so dropping the last reference to one moves its contents
+/// onto an explicit work list instead.
+impl Drop for Value {
+    fn drop(&mut self) {
+        let mut pending = Vec::new();
+        self.take_contents(&mut pending);
+        while let Some(mut value) = pending.pop() {
+            value.take_contents(&mut pending);
+        }
+    }
+}